@@ -0,0 +1,248 @@
+use crate::client::BoxError;
+use crate::health::{HealthState, Reason};
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Alertmanager the checks push alerts to, without waiting for a Prometheus rule evaluation
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AlertmanagerConfig {
+    /// Base URL, alerts are posted to `{url}/api/v2/alerts`
+    pub url: String,
+    /// Seconds between re-sends of firing alerts, Alertmanager resolves alerts that aren't re-sent
+    #[serde(default = "default_resend_interval")]
+    pub resend_interval: u64,
+    /// `alertname` label of the alerts
+    #[serde(default = "default_alertname")]
+    pub alertname: String,
+    /// Labels added to every alert
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// URL of this monitor as reachable from Alertmanager's UI, alerts link to `/health/{name}`
+    pub external_url: Option<String>,
+}
+
+fn default_resend_interval() -> u64 {
+    60
+}
+
+fn default_alertname() -> String {
+    "SubgraphHealth".to_string()
+}
+
+impl AlertmanagerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if reqwest::Url::parse(&self.url).is_err() {
+            return Err(format!("invalid alertmanager url {:?}", self.url));
+        }
+        if self.resend_interval == 0 {
+            return Err("alertmanager resend_interval must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Alert in the format of Alertmanager's `/api/v2/alerts`
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PostableAlert {
+    labels: BTreeMap<String, String>,
+    annotations: BTreeMap<String, String>,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generator_url: Option<String>,
+}
+
+/// Alerts to post for a target, and what Alertmanager knows once they are posted
+pub struct Update {
+    target: String,
+    firing: Vec<(Reason, DateTime<Utc>)>,
+    alerts: Vec<PostableAlert>,
+}
+
+/// Reasons of a target Alertmanager was last told are firing, with the time each started
+struct Pushed {
+    firing: Vec<(Reason, DateTime<Utc>)>,
+    sent_at: Instant,
+}
+
+pub struct Alertmanager {
+    config: AlertmanagerConfig,
+    /// Host of the Alertmanager, the `endpoint` label of the request metrics
+    pub endpoint: String,
+    pushed: Mutex<HashMap<String, Pushed>>,
+}
+
+fn severity(reason: Reason) -> &'static str {
+    match reason.severity() {
+        HealthState::Down => "critical",
+        _ => "warning",
+    }
+}
+
+impl Alertmanager {
+    pub fn new(config: AlertmanagerConfig) -> Alertmanager {
+        Alertmanager {
+            endpoint: crate::chain_head::provider_label(&config.url),
+            config,
+            pushed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Alerts for the reported state of a target, one per reason: new reasons fire, reasons that no
+    /// longer apply are resolved and firing alerts are re-sent every `resend_interval`. `None` if
+    /// Alertmanager is up to date.
+    pub fn update(
+        &self,
+        target: &str,
+        interval: u64,
+        state: HealthState,
+        reasons: &[Reason],
+        description: &str,
+        now: DateTime<Utc>,
+    ) -> Option<Update> {
+        let pushed = self.pushed.lock().unwrap();
+        let previous = pushed.get(target);
+        let previous_firing = previous.map_or(&[][..], |p| &p.firing[..]);
+        let started = |reason: &Reason| previous_firing.iter().find(|(r, _)| r == reason).map(|&(_, at)| at);
+
+        // while the reported state waits for `rise` healthy checks, the alerts stay as they were
+        let reasons: Vec<Reason> = if state == HealthState::Healthy {
+            Vec::new()
+        } else if reasons.is_empty() {
+            previous_firing.iter().map(|&(r, _)| r).collect()
+        } else {
+            reasons.to_vec()
+        };
+        let firing: Vec<(Reason, DateTime<Utc>)> = reasons.iter().map(|r| (*r, started(r).unwrap_or(now))).collect();
+        let resolved: Vec<(Reason, DateTime<Utc>)> = previous_firing
+            .iter()
+            .filter(|(r, _)| !reasons.contains(r))
+            .copied()
+            .collect();
+
+        let changed = !resolved.is_empty() || firing.len() != previous_firing.len();
+        let resend = previous.is_none_or(|p| p.sent_at.elapsed().as_secs() >= self.config.resend_interval);
+        if !changed && (firing.is_empty() || !resend) {
+            return None;
+        }
+
+        // alerts that aren't re-sent end on their own, in case the monitor goes away
+        let hold = chrono::Duration::seconds(4 * self.config.resend_interval.max(interval) as i64);
+        let alerts = firing
+            .iter()
+            .map(|&(reason, starts_at)| self.alert(target, reason, description, starts_at, now + hold))
+            .chain(resolved.iter().map(|&(reason, starts_at)| self.alert(target, reason, description, starts_at, now)))
+            .collect();
+        Some(Update { target: target.to_string(), firing, alerts })
+    }
+
+    fn alert(
+        &self,
+        target: &str,
+        reason: Reason,
+        description: &str,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+    ) -> PostableAlert {
+        let mut labels = self.config.labels.clone();
+        labels.insert("alertname".to_string(), self.config.alertname.clone());
+        labels.insert("target".to_string(), target.to_string());
+        labels.insert("reason".to_string(), reason.as_str().to_string());
+        labels.insert("severity".to_string(), severity(reason).to_string());
+
+        let mut annotations = BTreeMap::new();
+        let summary = format!("{} is {}: {}", target, reason.severity().as_str(), reason.as_str());
+        annotations.insert("summary".to_string(), summary);
+        annotations.insert("description".to_string(), description.to_string());
+
+        PostableAlert {
+            labels,
+            annotations,
+            starts_at,
+            ends_at,
+            generator_url: self
+                .config
+                .external_url
+                .as_ref()
+                .map(|url| format!("{}/health/{}", url.trim_end_matches('/'), target)),
+        }
+    }
+
+    pub async fn post(&self, client: &Client, update: &Update) -> Result<(), BoxError> {
+        client
+            .post(format!("{}/api/v2/alerts", self.config.url.trim_end_matches('/')))
+            .json(&update.alerts)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Records a posted update, failed updates are retried with the next check
+    pub fn commit(&self, update: Update) {
+        let mut pushed = self.pushed.lock().unwrap();
+        if update.firing.is_empty() {
+            pushed.remove(&update.target);
+        } else {
+            pushed.insert(update.target, Pushed { firing: update.firing, sent_at: Instant::now() });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::at;
+
+    fn alertmanager() -> Alertmanager {
+        Alertmanager::new(AlertmanagerConfig {
+            url: "http://127.0.0.1:9093".to_string(),
+            resend_interval: 60,
+            alertname: default_alertname(),
+            labels: BTreeMap::new(),
+            external_url: None,
+        })
+    }
+
+    /// Reasons of the alerts of an update, with whether each is still firing at `now`
+    fn alerts(update: &Update, now: DateTime<Utc>) -> Vec<(String, bool)> {
+        update.alerts.iter().map(|a| (a.labels["reason"].clone(), a.ends_at > now)).collect()
+    }
+
+    #[test]
+    fn update_resolves_a_swapped_reason() {
+        let am = alertmanager();
+        let update = am.update("a", 10, HealthState::Degraded, &[Reason::Lagging], "", at(0)).unwrap();
+        am.commit(update);
+
+        let update = am.update("a", 10, HealthState::Degraded, &[Reason::RpcUnreachable], "", at(10)).unwrap();
+        let expected = vec![("rpc_unreachable".to_string(), true), ("lagging".to_string(), false)];
+        assert_eq!(alerts(&update, at(10)), expected);
+        // the resolved alert keeps its start
+        assert_eq!(update.alerts[1].starts_at, at(0));
+        am.commit(update);
+
+        assert!(am.update("a", 10, HealthState::Degraded, &[Reason::RpcUnreachable], "", at(20)).is_none());
+    }
+
+    #[test]
+    fn update_keeps_firing_while_recovery_waits_for_rise() {
+        let am = alertmanager();
+        am.commit(am.update("a", 10, HealthState::Down, &[Reason::SubgraphUnreachable], "", at(0)).unwrap());
+
+        // the check is healthy but the reported state is still down
+        assert!(am.update("a", 10, HealthState::Down, &[], "", at(10)).is_none());
+
+        let update = am.update("a", 10, HealthState::Healthy, &[], "", at(20)).unwrap();
+        assert_eq!(alerts(&update, at(20)), vec![("subgraph_unreachable".to_string(), false)]);
+        assert!(update.firing.is_empty());
+        am.commit(update);
+        assert!(am.update("a", 10, HealthState::Healthy, &[], "", at(30)).is_none());
+    }
+}
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+
+/// How the chain head is derived from the heads reported by several RPC providers
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// Highest reported head
+    Max,
+    /// Median of the reported heads
+    Median,
+    /// Highest head reported by at least `quorum` providers
+    Quorum,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ChainHeadConfig {
+    #[serde(default = "default_strategy")]
+    pub strategy: Strategy,
+    /// Number of providers that must have reached a head for the `quorum` strategy
+    #[serde(default = "default_quorum")]
+    pub quorum: usize,
+    /// Blocks a provider may differ from the selected head before it is flagged, and the subgraph
+    /// may be ahead of it before `negative_lag` is reported
+    #[serde(default = "default_tolerance")]
+    pub tolerance: i64,
+    /// Seconds without the chain head advancing before the RPC view is considered stale,
+    /// pick according to the chain's block time
+    #[serde(default = "default_stale_after")]
+    pub stale_after: u64,
+}
+
+fn default_strategy() -> Strategy {
+    Strategy::Max
+}
+
+fn default_quorum() -> usize {
+    1
+}
+
+fn default_tolerance() -> i64 {
+    5
+}
+
+fn default_stale_after() -> u64 {
+    300
+}
+
+impl Default for ChainHeadConfig {
+    fn default() -> Self {
+        ChainHeadConfig {
+            strategy: default_strategy(),
+            quorum: default_quorum(),
+            tolerance: default_tolerance(),
+            stale_after: default_stale_after(),
+        }
+    }
+}
+
+impl ChainHeadConfig {
+    pub fn validate(&self, providers: usize) -> Result<(), String> {
+        if self.strategy == Strategy::Quorum && (self.quorum == 0 || self.quorum > providers) {
+            return Err(format!("quorum must be between 1 and the number of RPC providers ({})", providers));
+        }
+        if self.tolerance < 0 {
+            return Err("chain head tolerance must not be negative".to_string());
+        }
+        if self.stale_after == 0 {
+            return Err("chain head stale_after must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+
+    /// Selects the chain head from the heads of the providers that answered,
+    /// `None` if too few answered for the strategy
+    pub fn select(&self, heads: &[i64]) -> Option<i64> {
+        let mut heads = heads.to_vec();
+        heads.sort_unstable_by(|a, b| b.cmp(a));
+        match self.strategy {
+            Strategy::Max => heads.first().copied(),
+            Strategy::Median => {
+                if heads.is_empty() {
+                    return None;
+                }
+                let mid = heads.len() / 2;
+                if heads.len().is_multiple_of(2) {
+                    Some((heads[mid - 1] + heads[mid]) / 2)
+                } else {
+                    Some(heads[mid])
+                }
+            },
+            // sorted descending, the k-th entry is the highest head k providers have reached
+            Strategy::Quorum => heads.get(self.quorum - 1).copied(),
+        }
+    }
+}
+
+/// Result of asking a single RPC provider for its head
+#[derive(Serialize, Clone, Debug)]
+pub struct ProviderStatus {
+    pub provider: String,
+    pub head: Option<i64>,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+    /// Set when the provider's head differs from the selected head by more than the tolerance
+    pub disagrees: bool,
+    /// Chain ID reported by `eth_chainId`, `None` until the provider answered it
+    pub chain_id: Option<u64>,
+    /// Set when the provider is on another chain than the subgraph indexes, its head is ignored
+    pub wrong_chain: bool,
+}
+
+/// Label identifying a provider in metrics and on the dashboard, the host so that API keys
+/// in the URL path don't leak
+pub fn provider_label(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) => match (parsed.host_str(), parsed.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            _ => url.to_string(),
+        },
+        Err(_) => url.to_string(),
+    }
+}
+
+/// Provider labels for a list of URLs, made unique when several URLs share a host
+pub fn provider_labels(urls: &[String]) -> Vec<String> {
+    let hosts: Vec<String> = urls.iter().map(|url| provider_label(url)).collect();
+    hosts
+        .iter()
+        .enumerate()
+        .map(|(i, host)| {
+            if hosts.iter().filter(|h| *h == host).count() > 1 {
+                format!("{}#{}", host, i)
+            } else {
+                host.clone()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(strategy: Strategy, quorum: usize) -> ChainHeadConfig {
+        ChainHeadConfig { strategy, quorum, ..ChainHeadConfig::default() }
+    }
+
+    #[test]
+    fn select_max() {
+        assert_eq!(config(Strategy::Max, 1).select(&[100, 103, 101]), Some(103));
+        assert_eq!(config(Strategy::Max, 1).select(&[]), None);
+    }
+
+    #[test]
+    fn select_median_of_odd_and_even_counts() {
+        let median = config(Strategy::Median, 1);
+        assert_eq!(median.select(&[100, 110, 103]), Some(103));
+        // the mean of the middle two, rounded down
+        assert_eq!(median.select(&[100, 104, 101, 110]), Some(102));
+        assert_eq!(median.select(&[100, 103]), Some(101));
+        assert_eq!(median.select(&[]), None);
+    }
+
+    #[test]
+    fn select_quorum_needs_enough_answers() {
+        let quorum = config(Strategy::Quorum, 2);
+        assert_eq!(quorum.select(&[100, 105, 103]), Some(103));
+        assert_eq!(quorum.select(&[105, 105]), Some(105));
+        assert_eq!(quorum.select(&[105]), None);
+        assert_eq!(quorum.select(&[]), None);
+    }
+
+    #[test]
+    fn validate_rejects_a_quorum_beyond_the_providers() {
+        assert!(config(Strategy::Quorum, 3).validate(2).is_err());
+        assert!(config(Strategy::Quorum, 0).validate(2).is_err());
+        assert!(config(Strategy::Quorum, 2).validate(2).is_ok());
+    }
+}
@@ -0,0 +1,206 @@
+use prometheus::{Histogram, IntCounter, IntCounterVec};
+use reqwest::Client;
+use serde::Deserialize;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Settings for the HTTP client shared by all targets
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Connect timeout in seconds
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: f64,
+    /// Timeout for a whole request in seconds
+    #[serde(default = "default_request_timeout")]
+    pub request_timeout: f64,
+    /// Number of retries after a failed request
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Delay before the first retry in seconds, doubled on every further retry
+    #[serde(default = "default_backoff")]
+    pub backoff: f64,
+    /// Upper bound for the retry delay in seconds
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff: f64,
+}
+
+fn default_connect_timeout() -> f64 {
+    5.0
+}
+
+fn default_request_timeout() -> f64 {
+    10.0
+}
+
+fn default_retries() -> u32 {
+    2
+}
+
+fn default_backoff() -> f64 {
+    0.5
+}
+
+fn default_max_backoff() -> f64 {
+    5.0
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            connect_timeout: default_connect_timeout(),
+            request_timeout: default_request_timeout(),
+            retries: default_retries(),
+            backoff: default_backoff(),
+            max_backoff: default_max_backoff(),
+        }
+    }
+}
+
+impl HttpConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.connect_timeout <= 0.0 || self.request_timeout <= 0.0 {
+            return Err("http timeouts must be greater than 0".to_string());
+        }
+        if self.backoff < 0.0 || self.max_backoff < self.backoff {
+            return Err("http backoff must be positive and not exceed max_backoff".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn build_client(&self) -> reqwest::Result<Client> {
+        Client::builder()
+            .connect_timeout(Duration::from_secs_f64(self.connect_timeout))
+            .timeout(Duration::from_secs_f64(self.request_timeout))
+            .build()
+    }
+
+    /// Exponential backoff with equal jitter: half the delay is fixed, the other half random
+    fn retry_delay(&self, retry: u32) -> Duration {
+        let delay = (self.backoff * 2f64.powi(retry as i32)).min(self.max_backoff);
+        Duration::from_secs_f64(delay / 2.0 + rand::random::<f64>() * delay / 2.0)
+    }
+}
+
+/// Error a GraphQL endpoint reported in the `errors` of its response
+#[derive(Debug)]
+pub struct GraphQLError(pub String);
+
+impl std::fmt::Display for GraphQLError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "GraphQL error: {}", self.0)
+    }
+}
+
+impl std::error::Error for GraphQLError {}
+
+#[derive(Deserialize)]
+struct GraphQLMessage {
+    message: String,
+}
+
+/// Response of a GraphQL endpoint, errors are reported alongside or instead of the data
+#[derive(Deserialize)]
+pub struct GraphQLResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQLMessage>,
+}
+
+impl<T> GraphQLResponse<T> {
+    pub fn into_data(self) -> Result<T, BoxError> {
+        if !self.errors.is_empty() {
+            let messages: Vec<String> = self.errors.into_iter().map(|e| e.message).collect();
+            return Err(GraphQLError(messages.join("; ")).into());
+        }
+        self.data.ok_or_else(|| GraphQLError("response without data".to_string()).into())
+    }
+}
+
+/// Drops the URL from a request error, RPC and gateway URLs may carry API keys
+pub fn without_url(error: BoxError) -> BoxError {
+    match error.downcast::<reqwest::Error>() {
+        Ok(e) => Box::new(e.without_url()),
+        Err(e) => e,
+    }
+}
+
+/// Class of a failed request, the `class` label of the failure counter
+pub fn error_class(error: &BoxError) -> &'static str {
+    if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+        if e.is_timeout() {
+            "timeout"
+        } else if e.is_connect() {
+            "connect"
+        } else if e.is_status() {
+            "http_status"
+        } else if e.is_decode() {
+            "decode"
+        } else {
+            "other"
+        }
+    } else if error.is::<GraphQLError>() {
+        "graphql"
+    } else if error.is::<std::num::ParseIntError>() {
+        "decode"
+    } else {
+        "other"
+    }
+}
+
+/// Metrics for a single query of a single target against a single endpoint
+pub struct RequestCounters {
+    pub attempts: IntCounter,
+    pub retries: IntCounter,
+    pub timeouts: IntCounter,
+    pub successes: IntCounter,
+    /// Duration of every attempt
+    pub duration: Histogram,
+    /// Failed attempts, labelled with `labels` and the error class
+    pub failures: IntCounterVec,
+    pub labels: [String; 3],
+}
+
+impl RequestCounters {
+    fn failure(&self, class: &str) -> IntCounter {
+        let [target, query, endpoint] = &self.labels;
+        self.failures.with_label_values(&[target.as_str(), query, endpoint, class])
+    }
+}
+
+/// Runs `op` until it succeeds or the configured number of retries is used up
+pub async fn with_retries<T, F, Fut>(config: &HttpConfig, counters: &RequestCounters, mut op: F) -> Result<T, BoxError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, BoxError>>,
+{
+    let mut retry = 0;
+    loop {
+        counters.attempts.inc();
+        let started = Instant::now();
+        let result = op().await;
+        counters.duration.observe(started.elapsed().as_secs_f64());
+        let error = match result {
+            Ok(value) => {
+                counters.successes.inc();
+                return Ok(value);
+            },
+            Err(e) => e,
+        };
+
+        let class = error_class(&error);
+        if class == "timeout" {
+            counters.timeouts.inc();
+        }
+        counters.failure(class).inc();
+        if retry >= config.retries {
+            return Err(error);
+        }
+
+        tokio::time::sleep(config.retry_delay(retry)).await;
+        retry += 1;
+        counters.retries.inc();
+    }
+}
@@ -0,0 +1,451 @@
+use crate::alertmanager::AlertmanagerConfig;
+use crate::chain_head::ChainHeadConfig;
+use crate::client::HttpConfig;
+use crate::health::{Startup, StatusCodes};
+use crate::index_node::SubgraphRef;
+use crate::notify::NotifierConfig;
+use crate::policy::PolicyConfig;
+use crate::store::StorageConfig;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub targets: Vec<TargetConfig>,
+    /// HTTP status codes returned by `/health` per state
+    #[serde(default)]
+    pub status_codes: StatusCodes,
+    /// Timeouts and retries of the shared HTTP client
+    #[serde(default)]
+    pub http: HttpConfig,
+    /// Number of past checks kept in memory per target for `/history`
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
+    /// SQLite store keeping checks and health transitions across restarts, disabled if unset
+    pub storage: Option<StorageConfig>,
+    /// Sinks notified when a target changes state
+    #[serde(default)]
+    pub notifiers: Vec<NotifierConfig>,
+    /// Deduplication, reminders and escalation of notifications
+    #[serde(default)]
+    pub notify_policy: PolicyConfig,
+    /// Alertmanager the checks push alerts to, disabled if unset
+    pub alertmanager: Option<AlertmanagerConfig>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TargetConfig {
+    /// Unique name, used in `/health/{name}` and as the `target` metric label
+    pub name: String,
+    /// Subgraph endpoint URL
+    pub subgraph_url: String,
+    /// graph-node index-node status endpoint (e.g. `http://graph-node:8030/graphql`) for indexing
+    /// health, errors and graph-node's own chain head
+    pub index_node_url: Option<String>,
+    /// Also track the pending version of the subgraph name while a new deployment syncs,
+    /// requires `index_node_url`
+    #[serde(default)]
+    pub track_pending_version: bool,
+    /// Chain ID the RPC providers must report, taken from the network graph-node reports
+    /// for the subgraph if unset
+    pub chain_id: Option<u64>,
+    /// RPC endpoint URLs, queried concurrently for the chain head
+    #[serde(alias = "rpc_url", deserialize_with = "one_or_many")]
+    pub rpc_urls: Vec<String>,
+    /// How the chain head is derived from several RPC providers
+    #[serde(default)]
+    pub chain_head: ChainHeadConfig,
+    /// Check interval in seconds
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// Intervals without a completed check before the monitor itself is reported stale
+    #[serde(default = "default_monitor_stale_intervals")]
+    pub monitor_stale_intervals: u32,
+    /// Lag thresholds deciding when the subgraph is considered behind
+    #[serde(default)]
+    pub thresholds: Thresholds,
+    /// Seconds without the synced block advancing before the subgraph is considered stalled
+    #[serde(default = "default_stall_after")]
+    pub stall_after: u64,
+    /// Seconds of history used to compute indexing and chain rates
+    #[serde(default = "default_rate_window")]
+    pub rate_window: u64,
+    /// Consecutive failed checks before a healthy target is reported unhealthy
+    #[serde(default = "default_one")]
+    pub fall: u32,
+    /// Consecutive healthy checks before an unhealthy target is reported healthy again
+    #[serde(default = "default_one")]
+    pub rise: u32,
+    /// Compare the synced block hash with the RPC to detect forked or wrong-chain indexing
+    #[serde(default = "default_true")]
+    pub verify_block_hash: bool,
+    /// Consecutive checks a block hash mismatch must persist before it is reported, a reorg at the
+    /// chain tip mismatches until graph-node reverts the block
+    #[serde(default = "default_hash_mismatch_checks")]
+    pub hash_mismatch_checks: u32,
+    /// Where the timestamp of the synced block is taken from to compute the lag in seconds
+    #[serde(default = "default_timestamp_source")]
+    pub block_timestamp: TimestampSource,
+    /// How the target is reported in `/health` until its first check completes
+    #[serde(default = "default_startup")]
+    pub startup: Startup,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampSource {
+    /// `_meta.block.timestamp`, falling back to the RPC if the subgraph doesn't report it
+    Meta,
+    /// `eth_getBlockByNumber` on the RPC
+    Rpc,
+}
+
+fn default_timestamp_source() -> TimestampSource {
+    TimestampSource::Rpc
+}
+
+/// Accepts a single string as well as a list of strings
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(url) => vec![url],
+        OneOrMany::Many(urls) => urls,
+    })
+}
+
+fn default_hash_mismatch_checks() -> u32 {
+    3
+}
+
+fn default_startup() -> Startup {
+    Startup::FailClosed
+}
+
+fn default_history_size() -> usize {
+    1440
+}
+
+fn default_interval() -> u64 {
+    60
+}
+
+fn default_monitor_stale_intervals() -> u32 {
+    3
+}
+
+fn default_stall_after() -> u64 {
+    600
+}
+
+fn default_rate_window() -> u64 {
+    600
+}
+
+fn default_critical_blocks() -> i64 {
+    20
+}
+
+fn default_one() -> u32 {
+    1
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Lag thresholds, evaluated in seconds when `warn_seconds` or `critical_seconds` is set
+/// and the lag in seconds is known from the block timestamp or the block time, in blocks otherwise
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Thresholds {
+    /// Blocks behind the chain head above which the lag is reported as a warning
+    pub warn_blocks: Option<i64>,
+    /// Blocks behind the chain head above which the subgraph is unhealthy
+    #[serde(default = "default_critical_blocks")]
+    pub critical_blocks: i64,
+    /// Seconds behind the chain head above which the lag is reported as a warning
+    pub warn_seconds: Option<f64>,
+    /// Seconds behind the chain head above which the subgraph is unhealthy
+    pub critical_seconds: Option<f64>,
+    /// Average block time in seconds, used to estimate the lag in seconds from blocks behind
+    /// when the timestamp of the synced block is unknown
+    pub block_time: Option<f64>,
+    /// Blocks graph-node's chain head may trail the RPC head before its block ingestor is
+    /// reported lagging, `critical_blocks` if unset
+    pub ingestor_blocks: Option<i64>,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            warn_blocks: None,
+            critical_blocks: default_critical_blocks(),
+            warn_seconds: None,
+            critical_seconds: None,
+            block_time: None,
+            ingestor_blocks: None,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum LagLevel {
+    Ok,
+    Warn,
+    Critical,
+}
+
+impl LagLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LagLevel::Ok => "ok",
+            LagLevel::Warn => "warn",
+            LagLevel::Critical => "critical",
+        }
+    }
+}
+
+impl Thresholds {
+    fn uses_seconds(&self) -> bool {
+        self.warn_seconds.is_some() || self.critical_seconds.is_some()
+    }
+
+    pub fn ingestor_blocks(&self) -> i64 {
+        self.ingestor_blocks.unwrap_or(self.critical_blocks)
+    }
+
+    /// Estimated lag in seconds, if the block time is known
+    pub fn lag_seconds(&self, blocks_behind: i64) -> Option<f64> {
+        self.block_time.map(|block_time| blocks_behind as f64 * block_time)
+    }
+
+    pub fn level(&self, blocks_behind: i64, lag_seconds: Option<f64>) -> LagLevel {
+        if let Some(level) = lag_seconds.and_then(|lag| self.level_in_seconds(lag)) {
+            return level;
+        }
+        classify(
+            blocks_behind as f64,
+            self.warn_blocks.map(|b| b as f64),
+            Some(self.critical_blocks as f64),
+        )
+    }
+
+    /// Level of a lag in seconds, `None` without seconds thresholds
+    pub fn level_in_seconds(&self, lag_seconds: f64) -> Option<LagLevel> {
+        self.uses_seconds().then(|| classify(lag_seconds, self.warn_seconds, self.critical_seconds))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(block_time) = self.block_time {
+            if block_time <= 0.0 {
+                return Err("block_time must be greater than 0".to_string());
+            }
+        }
+        if matches!(self.warn_blocks, Some(warn) if warn > self.critical_blocks) {
+            return Err("warn_blocks must not exceed critical_blocks".to_string());
+        }
+        if let (Some(warn), Some(critical)) = (self.warn_seconds, self.critical_seconds) {
+            if warn > critical {
+                return Err("warn_seconds must not exceed critical_seconds".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn classify(lag: f64, warn: Option<f64>, critical: Option<f64>) -> LagLevel {
+    if matches!(critical, Some(critical) if lag > critical) {
+        LagLevel::Critical
+    } else if matches!(warn, Some(warn) if lag > warn) {
+        LagLevel::Warn
+    } else {
+        LagLevel::Ok
+    }
+}
+
+impl TargetConfig {
+    fn from_args(subgraph_url: String, rpc_urls: Vec<String>, interval: u64) -> Self {
+        TargetConfig {
+            name: "default".to_string(),
+            subgraph_url,
+            index_node_url: None,
+            chain_id: None,
+            track_pending_version: false,
+            rpc_urls,
+            chain_head: ChainHeadConfig::default(),
+            interval,
+            monitor_stale_intervals: default_monitor_stale_intervals(),
+            thresholds: Thresholds::default(),
+            stall_after: default_stall_after(),
+            rate_window: default_rate_window(),
+            fall: default_one(),
+            rise: default_one(),
+            verify_block_hash: true,
+            hash_mismatch_checks: default_hash_mismatch_checks(),
+            block_timestamp: default_timestamp_source(),
+            startup: default_startup(),
+        }
+    }
+}
+
+impl Config {
+    /// Builds a config with a single target when no config file is given
+    pub fn from_args(subgraph_url: String, rpc_urls: Vec<String>, interval: u64) -> Self {
+        Config {
+            targets: vec![TargetConfig::from_args(subgraph_url, rpc_urls, interval)],
+            status_codes: StatusCodes::default(),
+            http: HttpConfig::default(),
+            history_size: default_history_size(),
+            storage: None,
+            notifiers: Vec::new(),
+            notify_policy: PolicyConfig::default(),
+            alertmanager: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let config: Config = toml::from_str(&contents)
+            .map_err(|e| format!("failed to parse {}: {}", path.display(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.targets.is_empty() {
+            return Err("config must declare at least one [[targets]] entry".to_string());
+        }
+
+        self.status_codes.validate()?;
+        self.http.validate()?;
+        if let Some(storage) = &self.storage {
+            storage.validate()?;
+        }
+        if let Some(alertmanager) = &self.alertmanager {
+            alertmanager.validate()?;
+        }
+
+        let mut names = HashSet::new();
+        for target in &self.targets {
+            if target.name.is_empty() || target.name.contains('/') {
+                return Err(format!("invalid target name {:?}", target.name));
+            }
+            if !names.insert(target.name.as_str()) {
+                return Err(format!("duplicate target name {:?}", target.name));
+            }
+            if target.interval == 0 {
+                return Err(format!("target {:?}: interval must be greater than 0", target.name));
+            }
+            if target.stall_after == 0 {
+                return Err(format!("target {:?}: stall_after must be greater than 0", target.name));
+            }
+            if target.monitor_stale_intervals == 0 {
+                return Err(format!("target {:?}: monitor_stale_intervals must be at least 1", target.name));
+            }
+            if target.fall == 0 || target.rise == 0 {
+                return Err(format!("target {:?}: fall and rise must be at least 1", target.name));
+            }
+            if target.hash_mismatch_checks == 0 {
+                return Err(format!("target {:?}: hash_mismatch_checks must be at least 1", target.name));
+            }
+            if target.rpc_urls.is_empty() {
+                return Err(format!("target {:?}: at least one RPC URL is required", target.name));
+            }
+            if target.index_node_url.is_some() && SubgraphRef::from_url(&target.subgraph_url).is_none() {
+                return Err(format!(
+                    "target {:?}: index_node_url requires a /subgraphs/name/... or /subgraphs/id/... subgraph_url",
+                    target.name
+                ));
+            }
+            if target.track_pending_version
+                && (target.index_node_url.is_none()
+                    || !matches!(SubgraphRef::from_url(&target.subgraph_url), Some(SubgraphRef::Name(_))))
+            {
+                return Err(format!(
+                    "target {:?}: track_pending_version requires index_node_url and a /subgraphs/name/... subgraph_url",
+                    target.name
+                ));
+            }
+            if target.startup == Startup::Restore && self.storage.is_none() {
+                return Err(format!("target {:?}: startup = \"restore\" requires [storage]", target.name));
+            }
+            target
+                .chain_head
+                .validate(target.rpc_urls.len())
+                .map_err(|e| format!("target {:?}: {}", target.name, e))?;
+            target
+                .thresholds
+                .validate()
+                .map_err(|e| format!("target {:?}: {}", target.name, e))?;
+        }
+
+        let mut notifiers = HashSet::new();
+        for notifier in &self.notifiers {
+            if !notifiers.insert(notifier.name.as_str()) {
+                return Err(format!("duplicate notifier name {:?}", notifier.name));
+            }
+            if let Some(unknown) = notifier.targets.iter().find(|t| !names.contains(t.as_str())) {
+                return Err(format!("notifier {:?}: unknown target {:?}", notifier.name, unknown));
+            }
+            notifier.validate()?;
+        }
+        self.notify_policy.validate(&self.notifiers)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_in_blocks_without_seconds_thresholds() {
+        let thresholds = Thresholds { warn_blocks: Some(10), critical_blocks: 20, ..Thresholds::default() };
+        assert_eq!(thresholds.level(10, Some(9999.0)), LagLevel::Ok);
+        assert_eq!(thresholds.level(11, None), LagLevel::Warn);
+        assert_eq!(thresholds.level(21, None), LagLevel::Critical);
+        // without a warning threshold the lag is ok up to the critical one
+        assert_eq!(Thresholds::default().level(20, None), LagLevel::Ok);
+    }
+
+    #[test]
+    fn level_in_seconds_falls_back_to_blocks_without_a_lag_in_seconds() {
+        let thresholds = Thresholds {
+            warn_seconds: Some(60.0),
+            critical_seconds: Some(300.0),
+            ..Thresholds::default()
+        };
+        assert_eq!(thresholds.level(100, Some(30.0)), LagLevel::Ok);
+        assert_eq!(thresholds.level(0, Some(61.0)), LagLevel::Warn);
+        assert_eq!(thresholds.level(0, Some(301.0)), LagLevel::Critical);
+        assert_eq!(thresholds.level(21, None), LagLevel::Critical);
+        assert_eq!(thresholds.level(5, None), LagLevel::Ok);
+    }
+
+    #[test]
+    fn level_in_seconds_only_with_seconds_thresholds() {
+        let seconds = Thresholds { critical_seconds: Some(300.0), ..Thresholds::default() };
+        assert_eq!(seconds.level_in_seconds(301.0), Some(LagLevel::Critical));
+        assert_eq!(seconds.level_in_seconds(200.0), Some(LagLevel::Ok));
+        assert_eq!(Thresholds::default().level_in_seconds(301.0), None);
+    }
+
+    #[test]
+    fn lag_seconds_estimated_from_the_block_time() {
+        let thresholds = Thresholds { block_time: Some(1.8), ..Thresholds::default() };
+        assert_eq!(thresholds.lag_seconds(10), Some(18.0));
+        assert_eq!(Thresholds::default().lag_seconds(10), None);
+    }
+}
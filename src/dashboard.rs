@@ -1,16 +1,250 @@
 use actix_web::{web, HttpResponse, Responder};
-use crate::AppState;
-pub async fn render_dashboard(app_state: web::Data<AppState>) -> impl Responder {
+use crate::health::HealthState;
+use crate::{AppState, Target};
+
+const COPY_ICON: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round">
+                            <rect x="9" y="9" width="13" height="13" rx="2" ry="2"></rect>
+                            <path d="M5 15H4a2 2 0 0 1-2-2V4a2 2 0 0 1 2-2h9a2 2 0 0 1 2 2v1"></path>
+                        </svg>"#;
+
+/// Formats seconds as e.g. `2h 5m` or `45s`
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Escapes text from graph-node, error messages may contain markup
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_target(index: usize, target: &Target) -> String {
     // minimize mutex lock duration by cloning only what's needed
-    let status = {
-        let status_guard = app_state.status.lock().unwrap();
-        status_guard.clone()
+    let status = target.current_status();
+    let (health_class, health_text) = match status.state {
+        HealthState::Unknown => ("status-unknown", "Initialising"),
+        HealthState::Healthy => ("status-healthy", "Healthy"),
+        HealthState::Degraded => ("status-degraded", "Degraded"),
+        HealthState::Down => ("status-down", "Down"),
+    };
+    let reasons = status
+        .reasons
+        .iter()
+        .map(|r| format!(r#"<span class="reason">{}</span>"#, r.as_str()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let indexing_rate = match (status.indexing_rate, status.chain_rate) {
+        (Some(indexing), Some(chain)) => format!("{:.2} / {:.2}", indexing, chain),
+        _ => "-".to_string(),
+    };
+    let eta = match status.eta_seconds {
+        _ if status.falling_behind => "Falling behind".to_string(),
+        Some(0.0) => "Synced".to_string(),
+        Some(eta) => format_duration(eta),
+        None => "-".to_string(),
+    };
+    let fatal_error = match &status.fatal_error {
+        Some(error) => format!(
+            r#"<div class="fatal-error">
+                <div class="panel-label">FATAL INDEXING ERROR</div>
+                <div class="fatal-error-location">block {block} &middot; {handler}</div>
+                <div class="fatal-error-message">{message}</div>
+            </div>"#,
+            block = error.block.as_ref().map_or("-".to_string(), |b| b.number.to_string()),
+            handler = escape_html(error.handler.as_deref().unwrap_or("unknown handler")),
+            message = escape_html(&error.message),
+        ),
+        None => String::new(),
+    };
+    let graph_node = match &status.indexing_status {
+        Some(indexing) if indexing.synced => format!("{}, synced", indexing.health.as_str()),
+        Some(indexing) => format!("{}, syncing", indexing.health.as_str()),
+        None => "-".to_string(),
     };
-    let (health_color, health_text_color, health_text) = if status.healthy {
-        ("#c9b16d", "#000000", "Healthy") // gold bg, black text
-    } else {
-        ("#c92d2d", "#ffffff", "Unhealthy") // rich red bg, white text
+    // current and pending deployment side by side while a new version syncs
+    let deployments = match (&status.deployment, &status.pending_version) {
+        (None, None) => String::new(),
+        (current, pending) => {
+            let pending = match pending {
+                Some(pending) => format!(
+                    r#"<div class="deployment"><span>pending</span><span>{}</span><span>{}</span><span>{}</span></div>"#,
+                    pending.subgraph,
+                    pending
+                        .chain()
+                        .and_then(|chain| chain.latest_block.as_ref())
+                        .map_or("-".to_string(), |block| block.number.to_string()),
+                    if pending.synced { "synced" } else { "syncing" },
+                ),
+                None => String::new(),
+            };
+            format!(
+                r#"<div class="panel">
+                <div class="panel-label">DEPLOYMENTS</div>
+                <div class="deployment"><span>current</span><span>{}</span><span>{}</span><span></span></div>
+                {}
+            </div>"#,
+                current.as_deref().unwrap_or("-"),
+                status.synced_block_height,
+                pending,
+            )
+        },
     };
+    // provider labels, the URLs may carry API keys
+    let rpc_urls = target
+        .providers
+        .iter()
+        .enumerate()
+        .map(|(i, url)| {
+            format!(
+                r#"<div class="url-container">
+                    <div class="panel-value" id="rpc-url-{index}-{i}">{url}</div>
+                    <button class="copy-button" onclick="copyToClipboard('rpc-url-{index}-{i}')">
+                        {icon}
+                    </button>
+                </div>"#,
+                index = index,
+                i = i,
+                url = url,
+                icon = COPY_ICON,
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n                ");
+    let providers = status
+        .rpc_providers
+        .iter()
+        .map(|p| {
+            let (class, head) = match (p.head, &p.error) {
+                _ if p.wrong_chain => (
+                    "provider provider-disagrees",
+                    format!("wrong chain {}", p.chain_id.unwrap_or_default()),
+                ),
+                (Some(head), _) if p.disagrees => ("provider provider-disagrees", head.to_string()),
+                (Some(head), _) => ("provider", head.to_string()),
+                (None, Some(error)) => ("provider provider-disagrees", format!("error: {}", error)),
+                (None, None) => ("provider provider-disagrees", "no head".to_string()),
+            };
+            format!(
+                r#"<div class="{}"><span>{}</span><span>{}</span><span>{} ms</span></div>"#,
+                class, p.provider, head, p.latency_ms
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n                ");
+
+    format!(
+        r#"<div class="card">
+            <h2><a href="/health/{name}">{name}</a></h2>
+            
+            <div>
+                <div class="panel-label">SUBGRAPH</div>
+                <div class="url-container">
+                    <div class="panel-value" id="subgraph-url-{index}">{subgraph_url}</div>
+                    <button class="copy-button" onclick="copyToClipboard('subgraph-url-{index}')">
+                        {icon}
+                    </button>
+                </div>
+            </div>
+            
+            <div>
+                <div class="panel-label">RPC ENDPOINT</div>
+                {rpc_urls}
+            </div>
+            
+            <div class="status-row">
+                <div class="status-label">STATUS:</div>
+                <div class="status-indicator {health_class}">{health_text}</div>
+            </div>
+            <div class="reasons">{reasons}</div>
+            {fatal_error}
+            
+            <div class="stats-grid">
+                <div class="stat-panel">
+                    <div class="stat-label">Synced Block</div>
+                    <div class="stat-value">{synced}</div>
+                </div>
+                
+                <div class="stat-panel">
+                    <div class="stat-label">Chain Head</div>
+                    <div class="stat-value">{head}</div>
+                </div>
+                
+                <div class="stat-panel">
+                    <div class="stat-label">Blocks Behind</div>
+                    <div class="stat-value">{behind}</div>
+                </div>
+                
+                <div class="stat-panel">
+                    <div class="stat-label">Indexing / Chain blocks/s</div>
+                    <div class="stat-value">{indexing_rate}</div>
+                </div>
+                
+                <div class="stat-panel">
+                    <div class="stat-label">Time To Synced</div>
+                    <div class="stat-value">{eta}</div>
+                </div>
+                
+                <div class="stat-panel">
+                    <div class="stat-label">Graph Node</div>
+                    <div class="stat-value">{graph_node}</div>
+                </div>
+                
+                <div class="stat-panel">
+                    <div class="stat-label">Ingestor Lag</div>
+                    <div class="stat-value">{ingestor_lag}</div>
+                </div>
+            </div>
+            
+            {deployments}
+            
+            <div class="panel">
+                <div class="panel-label">RPC PROVIDERS</div>
+                {providers}
+            </div>
+            
+            <div class="timestamp">Last checked: {last_checked}</div>
+        </div>"#,
+        name = target.config.name,
+        index = index,
+        subgraph_url = target.config.subgraph_url,
+        rpc_urls = rpc_urls,
+        providers = providers,
+        deployments = deployments,
+        icon = COPY_ICON,
+        health_class = health_class,
+        health_text = if status.restored {
+            format!("{} (restored)", health_text)
+        } else {
+            health_text.to_string()
+        },
+        reasons = reasons,
+        fatal_error = fatal_error,
+        synced = status.synced_block_height,
+        head = status.chain_head_block_height,
+        behind = status.blocks_behind,
+        indexing_rate = indexing_rate,
+        eta = eta,
+        graph_node = graph_node,
+        ingestor_lag = status.ingestor_lag_blocks.map_or("-".to_string(), |lag| lag.to_string()),
+        last_checked = status.last_checked,
+    )
+}
+
+pub async fn render_dashboard(app_state: web::Data<AppState>) -> impl Responder {
+    let cards: String = app_state
+        .targets
+        .iter()
+        .enumerate()
+        .map(|(i, target)| render_target(i, target))
+        .collect::<Vec<_>>()
+        .join("\n        ");
     
     HttpResponse::Ok().content_type("text/html").body(format!(
         r#"<!DOCTYPE html>
@@ -61,6 +295,9 @@ pub async fn render_dashboard(app_state: web::Data<AppState>) -> impl Responder
             margin-bottom: 1.5rem;
             border-left: 4px solid var(--accent-color);
         }}
+        .header {{
+            border-left: none;
+        }}
         h1 {{
             margin: 0 0 1.5rem 0;
             font-weight: 600;
@@ -70,6 +307,19 @@ pub async fn render_dashboard(app_state: web::Data<AppState>) -> impl Responder
             color: var(--accent-color);
             letter-spacing: 1px;
         }}
+        h2 {{
+            margin: 0 0 1rem 0;
+            font-weight: 600;
+            font-size: 1.4rem;
+            letter-spacing: 1px;
+        }}
+        h2 a {{
+            color: var(--text-color);
+            text-decoration: none;
+        }}
+        h2 a:hover {{
+            color: var(--accent-color);
+        }}
         .panel {{
             background-color: var(--panel-bg);
             border-radius: 0px;
@@ -140,11 +390,55 @@ pub async fn render_dashboard(app_state: web::Data<AppState>) -> impl Responder
             font-weight: 600;
             padding: 0.35rem 1.25rem;
             border-radius: 0px;
-            background-color: {};
-            color: {};
             display: inline-block;
             letter-spacing: 1px;
         }}
+        .status-healthy {{
+            background-color: #c9b16d; /* gold bg */
+            color: #000000; /* black text */
+        }}
+        .status-unknown {{
+            background-color: #5c6370; /* slate bg */
+            color: #ffffff; /* white text */
+        }}
+        .status-degraded {{
+            background-color: #d9822b; /* amber bg */
+            color: #000000; /* black text */
+        }}
+        .status-down {{
+            background-color: #c92d2d; /* rich red bg */
+            color: #ffffff; /* white text */
+        }}
+        .reasons {{
+            display: flex;
+            flex-wrap: wrap;
+            justify-content: flex-end;
+            gap: 0.5rem;
+            margin: -0.75rem 0 1.5rem 0;
+        }}
+        .reason {{
+            font-family: monospace;
+            font-size: 0.8rem;
+            color: var(--muted-color);
+            border: 1px solid #333;
+            padding: 2px 8px;
+        }}
+        .fatal-error {{
+            border: 1px solid #c92d2d;
+            border-left: 4px solid #c92d2d;
+            padding: 1rem;
+            margin-bottom: 1.5rem;
+        }}
+        .fatal-error-location {{
+            font-family: monospace;
+            color: #c92d2d;
+            margin-bottom: 0.5rem;
+        }}
+        .fatal-error-message {{
+            font-family: monospace;
+            white-space: pre-wrap;
+            word-break: break-word;
+        }}
         .stats-grid {{
             display: grid;
             grid-template-columns: repeat(auto-fill, minmax(240px, 1fr));
@@ -170,6 +464,25 @@ pub async fn render_dashboard(app_state: web::Data<AppState>) -> impl Responder
             color: var(--accent-color);
             font-family: monospace;
         }}
+        .provider {{
+            display: grid;
+            grid-template-columns: 2fr 2fr 1fr;
+            gap: 1rem;
+            font-family: monospace;
+            padding: 0.25rem 0;
+            word-break: break-all;
+        }}
+        .deployment {{
+            display: grid;
+            grid-template-columns: 1fr 4fr 1fr 1fr;
+            gap: 1rem;
+            font-family: monospace;
+            padding: 0.25rem 0;
+            word-break: break-all;
+        }}
+        .provider-disagrees {{
+            color: #c92d2d;
+        }}
         .timestamp {{
             font-size: 0.875rem;
             color: var(--muted-color);
@@ -224,64 +537,17 @@ pub async fn render_dashboard(app_state: web::Data<AppState>) -> impl Responder
 </head>
 <body>
     <div class="container">
-        <div class="card">
+        <div class="card header">
             <h1>Subgraph Monitor</h1>
             
-            <div>
-                <div class="panel-label">SUBGRAPH</div>
-                <div class="url-container">
-                    <div class="panel-value" id="subgraph-url">{}</div>
-                    <button class="copy-button" onclick="copyToClipboard('subgraph-url')">
-                        <svg xmlns="http://www.w3.org/2000/svg" width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round">
-                            <rect x="9" y="9" width="13" height="13" rx="2" ry="2"></rect>
-                            <path d="M5 15H4a2 2 0 0 1-2-2V4a2 2 0 0 1 2-2h9a2 2 0 0 1 2 2v1"></path>
-                        </svg>
-                    </button>
-                </div>
-            </div>
-            
-            <div>
-                <div class="panel-label">RPC ENDPOINT</div>
-                <div class="url-container">
-                    <div class="panel-value" id="rpc-url">{}</div>
-                    <button class="copy-button" onclick="copyToClipboard('rpc-url')">
-                        <svg xmlns="http://www.w3.org/2000/svg" width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round">
-                            <rect x="9" y="9" width="13" height="13" rx="2" ry="2"></rect>
-                            <path d="M5 15H4a2 2 0 0 1-2-2V4a2 2 0 0 1 2-2h9a2 2 0 0 1 2 2v1"></path>
-                        </svg>
-                    </button>
-                </div>
-            </div>
-            
-            <div class="status-row">
-                <div class="status-label">STATUS:</div>
-                <div class="status-indicator">{}</div>
-            </div>
-            
-            <div class="stats-grid">
-                <div class="stat-panel">
-                    <div class="stat-label">Synced Block</div>
-                    <div class="stat-value">{}</div>
-                </div>
-                
-                <div class="stat-panel">
-                    <div class="stat-label">Chain Head</div>
-                    <div class="stat-value">{}</div>
-                </div>
-                
-                <div class="stat-panel">
-                    <div class="stat-label">Blocks Behind</div>
-                    <div class="stat-value">{}</div>
-                </div>
-            </div>
-            
-            <div class="timestamp">Last checked: {}</div>
-            
             <div class="action-buttons">
                 <a href="/health" class="action-button">JSON Health</a>
+                <a href="/history" class="action-button">History</a>
                 <a href="/metrics" class="action-button">Prometheus Metrics</a>
             </div>
         </div>
+        
+        {}
     </div>
     
     <div class="footer">
@@ -313,14 +579,6 @@ pub async fn render_dashboard(app_state: web::Data<AppState>) -> impl Responder
     </script>
 </body>
 </html>"#,
-        health_color,               // 1
-        health_text_color,          // 2
-        app_state.subgraph_url,     // 3
-        app_state.rpc_url,          // 4
-        health_text,                // 5
-        status.synced_block_height, // 6
-        status.chain_head_block_height, // 7
-        status.blocks_behind,       // 8
-        status.last_checked         // 9
+        cards
     ))
 }
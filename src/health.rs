@@ -0,0 +1,276 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthState {
+    /// No check has completed since startup
+    Unknown,
+    Healthy,
+    Degraded,
+    Down,
+}
+
+impl HealthState {
+    pub const ALL: [HealthState; 4] = [
+        HealthState::Unknown,
+        HealthState::Healthy,
+        HealthState::Degraded,
+        HealthState::Down,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthState::Unknown => "unknown",
+            HealthState::Healthy => "healthy",
+            HealthState::Degraded => "degraded",
+            HealthState::Down => "down",
+        }
+    }
+}
+
+/// Machine-readable cause of a non-healthy state
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    SubgraphUnreachable,
+    IndexingErrors,
+    RpcUnreachable,
+    Lagging,
+    LagCritical,
+    BlockHashMismatch,
+    /// The chain head has not advanced for longer than `stale_after`
+    RpcStale,
+    /// The chain head is below the synced block, the RPC is behind the subgraph
+    NegativeLag,
+    /// The synced block has not advanced for longer than `stall_after`
+    Stalled,
+    /// The index-node status API failed or doesn't know the subgraph
+    IndexNodeUnreachable,
+    /// graph-node reports the subgraph `unhealthy`, it hit non-fatal errors
+    SubgraphUnhealthy,
+    /// graph-node reports the subgraph `failed`, a fatal error stopped indexing
+    SubgraphFailed,
+    /// graph-node's chain head trails the RPC head by more than `ingestor_blocks`, the block
+    /// ingestor rather than the subgraph is behind
+    IngestorLagging,
+    /// An RPC provider is on another chain than the subgraph indexes, a misconfiguration
+    ChainMismatch,
+    /// No check has completed for `monitor_stale_intervals` intervals, the checker is stuck or crashed
+    MonitorStale,
+}
+
+impl Reason {
+    pub const ALL: [Reason; 15] = [
+        Reason::SubgraphUnreachable,
+        Reason::IndexingErrors,
+        Reason::RpcUnreachable,
+        Reason::Lagging,
+        Reason::LagCritical,
+        Reason::BlockHashMismatch,
+        Reason::RpcStale,
+        Reason::NegativeLag,
+        Reason::Stalled,
+        Reason::IndexNodeUnreachable,
+        Reason::SubgraphUnhealthy,
+        Reason::SubgraphFailed,
+        Reason::IngestorLagging,
+        Reason::ChainMismatch,
+        Reason::MonitorStale,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Reason::SubgraphUnreachable => "subgraph_unreachable",
+            Reason::IndexingErrors => "indexing_errors",
+            Reason::RpcUnreachable => "rpc_unreachable",
+            Reason::Lagging => "lagging",
+            Reason::LagCritical => "lag_critical",
+            Reason::BlockHashMismatch => "block_hash_mismatch",
+            Reason::RpcStale => "rpc_stale",
+            Reason::NegativeLag => "negative_lag",
+            Reason::Stalled => "stalled",
+            Reason::IndexNodeUnreachable => "index_node_unreachable",
+            Reason::SubgraphUnhealthy => "subgraph_unhealthy",
+            Reason::SubgraphFailed => "subgraph_failed",
+            Reason::IngestorLagging => "ingestor_lagging",
+            Reason::ChainMismatch => "chain_mismatch",
+            Reason::MonitorStale => "monitor_stale",
+        }
+    }
+
+    /// The state a target is in when this reason applies
+    pub fn severity(&self) -> HealthState {
+        match self {
+            // without a trustworthy chain head we can't judge the lag, but the subgraph itself still serves
+            Reason::RpcUnreachable
+            | Reason::RpcStale
+            | Reason::NegativeLag
+            | Reason::Lagging
+            | Reason::IndexNodeUnreachable
+            | Reason::SubgraphUnhealthy
+            | Reason::IngestorLagging
+            | Reason::ChainMismatch => HealthState::Degraded,
+            Reason::SubgraphUnreachable
+            | Reason::IndexingErrors
+            | Reason::LagCritical
+            | Reason::BlockHashMismatch
+            | Reason::Stalled
+            | Reason::SubgraphFailed
+            | Reason::MonitorStale => HealthState::Down,
+        }
+    }
+}
+
+/// Worst state implied by a set of reasons
+pub fn state_for(reasons: &[Reason]) -> HealthState {
+    reasons
+        .iter()
+        .map(Reason::severity)
+        .max()
+        .unwrap_or(HealthState::Healthy)
+}
+
+/// Applies fall/rise hysteresis to an observed state: a healthy target is only reported unhealthy
+/// after `fall` consecutive failed checks, and only reported healthy again after `rise` consecutive
+/// healthy checks. Moves between degraded and down are not delayed.
+pub fn debounce(
+    current: HealthState,
+    observed: HealthState,
+    consecutive_failures: u32,
+    consecutive_successes: u32,
+    fall: u32,
+    rise: u32,
+) -> HealthState {
+    if observed == HealthState::Healthy {
+        if current == HealthState::Healthy || consecutive_successes >= rise {
+            HealthState::Healthy
+        } else {
+            current
+        }
+    } else if current != HealthState::Healthy || consecutive_failures >= fall {
+        observed
+    } else {
+        HealthState::Healthy
+    }
+}
+
+/// How a target is reported before its first check has completed
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Startup {
+    /// Report the unknown state as healthy, so a restarting monitor doesn't trigger failovers
+    FailOpen,
+    /// Report the unknown state as down
+    FailClosed,
+    /// Report the last persisted check until the first check completes, fail closed without one
+    Restore,
+}
+
+impl Startup {
+    /// The state an unknown state counts as for status codes and the aggregate state
+    pub fn resolve(&self, state: HealthState) -> HealthState {
+        match (self, state) {
+            (Startup::FailOpen, HealthState::Unknown) => HealthState::Healthy,
+            (_, HealthState::Unknown) => HealthState::Down,
+            (_, state) => state,
+        }
+    }
+}
+
+/// HTTP status codes returned by `/health` for each state, so load balancers can decide
+/// whether a degraded target should still receive traffic
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StatusCodes {
+    #[serde(default = "default_ok")]
+    pub healthy: u16,
+    #[serde(default = "default_ok")]
+    pub degraded: u16,
+    #[serde(default = "default_unavailable")]
+    pub down: u16,
+}
+
+fn default_ok() -> u16 {
+    200
+}
+
+fn default_unavailable() -> u16 {
+    503
+}
+
+impl Default for StatusCodes {
+    fn default() -> Self {
+        StatusCodes {
+            healthy: default_ok(),
+            degraded: default_ok(),
+            down: default_unavailable(),
+        }
+    }
+}
+
+impl StatusCodes {
+    pub fn for_state(&self, state: HealthState) -> u16 {
+        match state {
+            HealthState::Healthy => self.healthy,
+            HealthState::Degraded => self.degraded,
+            // an unknown state is resolved by the startup mode first, fail closed otherwise
+            HealthState::Down | HealthState::Unknown => self.down,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for code in [self.healthy, self.degraded, self.down] {
+            if actix_web::http::StatusCode::from_u16(code).is_err() {
+                return Err(format!("invalid HTTP status code {}", code));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use HealthState::{Degraded, Down, Healthy};
+
+    /// Reported states after each observed state, starting from `start`, with `fall` 3 and `rise` 2
+    fn run(start: HealthState, observed: &[HealthState]) -> Vec<HealthState> {
+        let (mut state, mut failures, mut successes) = (start, 0, 0);
+        observed
+            .iter()
+            .map(|&o| {
+                if o == Healthy {
+                    (failures, successes) = (0, successes + 1);
+                } else {
+                    (failures, successes) = (failures + 1, 0);
+                }
+                state = debounce(state, o, failures, successes, 3, 2);
+                state
+            })
+            .collect()
+    }
+
+    #[test]
+    fn debounce_goes_down_after_fall_failures() {
+        assert_eq!(run(Healthy, &[Down, Down, Down]), [Healthy, Healthy, Down]);
+        // a success in between starts the count over
+        assert_eq!(run(Healthy, &[Down, Down, Healthy, Down, Down]), [Healthy; 5]);
+    }
+
+    #[test]
+    fn debounce_recovers_after_rise_successes() {
+        assert_eq!(run(Down, &[Healthy, Healthy]), [Down, Healthy]);
+        assert_eq!(run(Down, &[Healthy, Down, Healthy]), [Down, Down, Down]);
+    }
+
+    #[test]
+    fn debounce_moves_between_unhealthy_states_at_once() {
+        assert_eq!(run(Degraded, &[Down, Degraded]), [Down, Degraded]);
+    }
+
+    #[test]
+    fn debounce_with_fall_and_rise_of_one_follows_the_checks() {
+        assert_eq!(debounce(Healthy, Down, 1, 0, 1, 1), Down);
+        assert_eq!(debounce(Down, Healthy, 0, 1, 1, 1), Healthy);
+    }
+}
@@ -0,0 +1,68 @@
+use crate::health::{HealthState, Reason};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Outcome of a single check, as kept in the history
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CheckRecord {
+    pub checked_at: DateTime<Utc>,
+    pub state: HealthState,
+    pub observed_state: HealthState,
+    pub reasons: Vec<Reason>,
+    pub synced_block_height: i64,
+    pub chain_head_block_height: i64,
+    pub blocks_behind: i64,
+    pub lag_seconds: Option<f64>,
+    /// Deployment served by the subgraph endpoint
+    #[serde(default)]
+    pub deployment: Option<String>,
+    /// Time taken by the subgraph status query, including retries
+    pub subgraph_latency_ms: u64,
+    /// Time taken by the slowest RPC provider, including retries
+    pub rpc_latency_ms: Option<u64>,
+    pub errors: Vec<String>,
+}
+
+/// Bounded ring buffer of past checks of one target, oldest first
+pub struct History {
+    records: VecDeque<CheckRecord>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, record: CheckRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn last(&self) -> Option<&CheckRecord> {
+        self.records.back()
+    }
+
+    /// The most recent `limit` records checked within `[from, to]`, oldest first
+    pub fn query(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>, limit: usize) -> Vec<CheckRecord> {
+        let mut records: Vec<CheckRecord> = self
+            .records
+            .iter()
+            .rev()
+            .filter(|r| from.is_none_or(|from| r.checked_at >= from) && to.is_none_or(|to| r.checked_at <= to))
+            .take(limit)
+            .cloned()
+            .collect();
+        records.reverse();
+        records
+    }
+}
@@ -0,0 +1,197 @@
+use crate::client::{BoxError, GraphQLResponse};
+use reqwest::Client;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// How a subgraph is looked up in the index-node API, derived from its query URL
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubgraphRef {
+    /// `/subgraphs/name/{name}`, resolved to its current version by graph-node
+    Name(String),
+    /// `/subgraphs/id/{deployment}`
+    Deployment(String),
+}
+
+impl SubgraphRef {
+    pub fn from_url(url: &str) -> Option<SubgraphRef> {
+        let path = reqwest::Url::parse(url).ok()?.path().trim_end_matches('/').to_string();
+        if let Some((_, name)) = path.split_once("/subgraphs/name/") {
+            return Some(SubgraphRef::Name(name.to_string()));
+        }
+        if let Some((_, id)) = path.split_once("/subgraphs/id/") {
+            return Some(SubgraphRef::Deployment(id.to_string()));
+        }
+        None
+    }
+}
+
+const STATUS_FIELDS: &str = "
+fragment status on SubgraphIndexingStatus {
+    subgraph
+    synced
+    health
+    fatalError { message handler block { number hash } }
+    nonFatalErrors { message handler block { number hash } }
+    chains { network chainHeadBlock { number hash } earliestBlock { number hash } latestBlock { number hash } }
+}";
+
+/// Indexing health as reported by graph-node
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubgraphHealth {
+    Healthy,
+    /// Non-fatal errors occurred, indexing continues
+    Unhealthy,
+    /// A fatal error stopped indexing
+    Failed,
+}
+
+impl SubgraphHealth {
+    pub const ALL: [SubgraphHealth; 3] = [SubgraphHealth::Healthy, SubgraphHealth::Unhealthy, SubgraphHealth::Failed];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubgraphHealth::Healthy => "healthy",
+            SubgraphHealth::Unhealthy => "unhealthy",
+            SubgraphHealth::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct BlockPointer {
+    #[serde(deserialize_with = "big_int")]
+    pub number: i64,
+    pub hash: Option<String>,
+}
+
+/// graph-node returns block numbers as `BigInt` strings
+fn big_int<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct SubgraphError {
+    pub message: String,
+    /// Mapping handler that raised the error
+    pub handler: Option<String>,
+    /// Block the error occurred at
+    pub block: Option<BlockPointer>,
+}
+
+impl std::fmt::Display for SubgraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(block) = &self.block {
+            write!(f, "block {}: ", block.number)?;
+        }
+        if let Some(handler) = &self.handler {
+            write!(f, "{}: ", handler)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct ChainStatus {
+    pub network: String,
+    /// Chain head as seen by graph-node's block ingestor
+    pub chain_head_block: Option<BlockPointer>,
+    /// First block the subgraph indexes
+    pub earliest_block: Option<BlockPointer>,
+    /// Last block the subgraph has indexed
+    pub latest_block: Option<BlockPointer>,
+}
+
+/// Status of a deployment from graph-node's index-node API
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct IndexingStatus {
+    /// Deployment ID
+    #[serde(rename(serialize = "deployment"))]
+    pub subgraph: String,
+    pub synced: bool,
+    pub health: SubgraphHealth,
+    /// Surfaced as `fatal_error` of the target status
+    #[serde(skip_serializing)]
+    pub fatal_error: Option<SubgraphError>,
+    #[serde(default)]
+    pub non_fatal_errors: Vec<SubgraphError>,
+    #[serde(default)]
+    pub chains: Vec<ChainStatus>,
+}
+
+impl IndexingStatus {
+    /// Status of the first chain, subgraphs index a single chain
+    pub fn chain(&self) -> Option<&ChainStatus> {
+        self.chains.first()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StatusData {
+    indexing_status_for_current_version: Option<IndexingStatus>,
+    indexing_status_for_pending_version: Option<IndexingStatus>,
+    #[serde(default)]
+    indexing_statuses: Vec<IndexingStatus>,
+}
+
+async fn query_status(client: &Client, url: &str, query: &str, variables: serde_json::Value) -> Result<StatusData, BoxError> {
+    let body = serde_json::json!({
+        "query": format!("{} {}", query, STATUS_FIELDS),
+        "variables": variables,
+    });
+
+    let res = client.post(url)
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<GraphQLResponse<StatusData>>()
+        .await?;
+
+    res.into_data()
+}
+
+/// Queries the index-node for the status of a subgraph, `None` if graph-node doesn't know it
+pub async fn query_indexing_status(
+    client: &Client,
+    url: &str,
+    subgraph: &SubgraphRef,
+) -> Result<Option<IndexingStatus>, BoxError> {
+    Ok(match subgraph {
+        SubgraphRef::Name(name) => query_status(
+            client,
+            url,
+            "query($name: String!) { indexingStatusForCurrentVersion(subgraphName: $name) { ...status } }",
+            serde_json::json!({ "name": name }),
+        )
+        .await?
+        .indexing_status_for_current_version,
+        SubgraphRef::Deployment(id) => query_status(
+            client,
+            url,
+            "query($ids: [String!]) { indexingStatuses(subgraphs: $ids) { ...status } }",
+            serde_json::json!({ "ids": [id] }),
+        )
+        .await?
+        .indexing_statuses
+        .into_iter()
+        .next(),
+    })
+}
+
+/// Queries the index-node for the version of a subgraph name that is syncing to replace
+/// the current one, `None` if there is none
+pub async fn query_pending_status(client: &Client, url: &str, name: &str) -> Result<Option<IndexingStatus>, BoxError> {
+    Ok(query_status(
+        client,
+        url,
+        "query($name: String!) { indexingStatusForPendingVersion(subgraphName: $name) { ...status } }",
+        serde_json::json!({ "name": name }),
+    )
+    .await?
+    .indexing_status_for_pending_version)
+}
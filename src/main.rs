@@ -1,13 +1,40 @@
-use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
+use alertmanager::{Alertmanager, Update};
+use chain_head::ProviderStatus;
 use clap::Parser;
-use prometheus::{IntGauge, Registry};
+use client::{BoxError, GraphQLResponse, HttpConfig, RequestCounters};
+use config::{Config, LagLevel, TargetConfig, Thresholds, TimestampSource};
+use health::{HealthState, Reason, Startup, StatusCodes};
+use history::{CheckRecord, History};
+use index_node::{IndexingStatus, SubgraphError, SubgraphHealth, SubgraphRef};
+use notify::{Event, EventKind, NotifierConfig};
+use policy::{Alert, AlertChange, Due, Outcome, Policy};
+use progress::{HeightTracker, RateWindow, SyncRates};
+use prometheus::{core::Collector, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use store::{DeploymentChange, Store, Transition};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use tokio::time;
+use std::time::{Duration, Instant};
+use tokio::time::{self, MissedTickBehavior};
 
+mod alertmanager;
+mod chain_head;
+mod client;
+mod config;
 mod dashboard;
+mod health;
+mod history;
+mod index_node;
+mod network;
+mod notify;
+mod policy;
+mod progress;
+mod store;
+#[cfg(test)]
+mod testing;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -16,9 +43,9 @@ struct Args {
     #[clap(short, long, default_value = "https://flare-query.sceptre.fi/subgraphs/name/sflr-subgraph")]
     endpoint: String,
 
-    /// RPC endpoint URL
+    /// RPC endpoint URL, repeat to query several providers
     #[clap(short, long, default_value = "https://flare.gateway.tenderly.co")]
-    rpc: String,
+    rpc: Vec<String>,
 
     /// Port to run the monitor on
     #[clap(short, long, default_value_t = 3000)]
@@ -27,20 +54,292 @@ struct Args {
     /// Check interval in seconds
     #[clap(short, long, default_value_t = 60)]
     interval: u64,
+
+    /// Path to a TOML file declaring the targets to monitor (replaces --endpoint, --rpc and --interval)
+    #[clap(short, long)]
+    config: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, Serialize)]
 struct SubgraphStatus {
-    healthy: bool,
+    /// Reported state, after fall/rise hysteresis, `unknown` until the first check completes
+    state: HealthState,
+    /// Set while the status is the last persisted check from before a restart
+    restored: bool,
+    /// State of the last check on its own
+    observed_state: HealthState,
+    /// Why the last check was not healthy, empty when it was
+    reasons: Vec<Reason>,
+    consecutive_failures: u32,
+    consecutive_successes: u32,
+    /// Consecutive checks whose synced block hash differed from the RPC's
+    consecutive_hash_mismatches: u32,
     synced_block_height: i64,
+    /// Seconds since the synced block last advanced
+    seconds_since_progress: Option<u64>,
     chain_head_block_height: i64,
+    /// Seconds since the chain head last advanced
+    seconds_since_head_advanced: Option<u64>,
     blocks_behind: i64,
+    /// Age of the synced block in seconds, from its timestamp or estimated from the block time
+    lag_seconds: Option<f64>,
+    /// How the lag compares to the configured thresholds
+    lag_level: LagLevel,
+    /// Blocks indexed per second over the rate window
+    indexing_rate: Option<f64>,
+    /// Blocks produced by the chain per second over the rate window
+    chain_rate: Option<f64>,
+    /// Estimated seconds until the subgraph reaches the chain head
+    eta_seconds: Option<f64>,
+    /// Set when the subgraph indexes slower than the chain produces blocks
+    falling_behind: bool,
+    /// Hash of the synced block as reported by the subgraph
+    block_hash: Option<String>,
+    /// Chain ID the RPC providers must report, configured or from the network graph-node indexes
+    expected_chain_id: Option<u64>,
+    /// Heads reported by each RPC provider in the last check
+    rpc_providers: Vec<ProviderStatus>,
+    /// Status from graph-node's index-node API, if `index_node_url` is set
+    indexing_status: Option<IndexingStatus>,
+    /// Error that stopped indexing, as reported by graph-node
+    fatal_error: Option<SubgraphError>,
+    /// Deployment served by the subgraph endpoint, from `_meta.deployment`
+    deployment: Option<String>,
+    /// Network the subgraph indexes, as reported by graph-node
+    network: Option<String>,
+    /// Blocks graph-node's chain head trails the RPC head
+    ingestor_lag_blocks: Option<i64>,
+    /// Blocks the subgraph trails graph-node's chain head, its lag without the ingestor lag
+    blocks_behind_graph_node: Option<i64>,
+    /// New version of the subgraph name syncing to replace the current one, if `track_pending_version` is set
+    pending_version: Option<IndexingStatus>,
     last_checked: String,
+    #[serde(skip)]
+    chain_head_progress: HeightTracker,
+    #[serde(skip)]
+    synced_progress: HeightTracker,
+    #[serde(skip)]
+    rates: RateWindow,
+    /// Labels `subgraph_info` is currently exported with
+    #[serde(skip)]
+    info_labels: Option<InfoLabels>,
+    /// Lag level notifiers were last told about, lag events wait for the reported state like transitions
+    #[serde(skip)]
+    notified_lag_level: LagLevel,
 }
 
-#[derive(Deserialize)]
-struct GraphQLResponse {
-    data: GraphQLData,
+impl SubgraphStatus {
+    /// Status before the first check, taken from `snapshot` when restoring the last persisted check
+    fn new(snapshot: Option<&CheckRecord>) -> Self {
+        let mut status = SubgraphStatus {
+            state: HealthState::Unknown,
+            restored: false,
+            observed_state: HealthState::Unknown,
+            reasons: Vec::new(),
+            consecutive_failures: 0,
+            consecutive_successes: 0,
+            consecutive_hash_mismatches: 0,
+            synced_block_height: 0,
+            seconds_since_progress: None,
+            chain_head_block_height: 0,
+            seconds_since_head_advanced: None,
+            blocks_behind: 0,
+            lag_seconds: None,
+            lag_level: LagLevel::Ok,
+            indexing_rate: None,
+            chain_rate: None,
+            eta_seconds: None,
+            falling_behind: false,
+            block_hash: None,
+            expected_chain_id: None,
+            rpc_providers: Vec::new(),
+            indexing_status: None,
+            fatal_error: None,
+            deployment: None,
+            network: None,
+            ingestor_lag_blocks: None,
+            blocks_behind_graph_node: None,
+            pending_version: None,
+            last_checked: "".to_string(),
+            chain_head_progress: HeightTracker::default(),
+            synced_progress: HeightTracker::default(),
+            rates: RateWindow::default(),
+            info_labels: None,
+            notified_lag_level: LagLevel::Ok,
+        };
+        if let Some(record) = snapshot {
+            status.state = record.state;
+            status.restored = true;
+            status.observed_state = record.observed_state;
+            status.reasons = record.reasons.clone();
+            status.synced_block_height = record.synced_block_height;
+            status.chain_head_block_height = record.chain_head_block_height;
+            status.blocks_behind = record.blocks_behind;
+            status.lag_seconds = record.lag_seconds;
+            status.deployment = record.deployment.clone();
+            // the first check debounces against the restored state
+            status.last_checked = record.checked_at.to_rfc3339();
+        }
+        status
+    }
+
+    /// Applies a check, debouncing the reported state, and tells what changed
+    fn apply(&mut self, config: &TargetConfig, now: chrono::DateTime<chrono::Utc>, check: Observation) -> CheckOutcome {
+        if check.observed_state == HealthState::Healthy {
+            self.consecutive_successes += 1;
+            self.consecutive_failures = 0;
+        } else {
+            self.consecutive_failures += 1;
+            self.consecutive_successes = 0;
+        }
+        // the first check is taken as is, there is nothing to debounce against yet, except with
+        // `fail_open` where the target counted as healthy and needs `fall` failures to go down
+        let first_check = self.last_checked.is_empty();
+        let first_since_startup = first_check || self.restored;
+        let previous_state = self.state;
+        self.state = if first_check && config.startup != Startup::FailOpen {
+            check.observed_state
+        } else {
+            health::debounce(
+                config.startup.resolve(self.state),
+                check.observed_state,
+                self.consecutive_failures,
+                self.consecutive_successes,
+                config.fall,
+                config.rise,
+            )
+        };
+        self.restored = false;
+        self.observed_state = check.observed_state;
+        self.reasons = check.reasons;
+        self.synced_block_height = check.synced_block;
+        self.seconds_since_progress = check.since_progress.map(|d| d.as_secs());
+        self.chain_head_block_height = check.chain_head;
+        self.seconds_since_head_advanced = check.since_head_advanced.map(|d| d.as_secs());
+        self.blocks_behind = check.blocks_behind;
+        self.lag_seconds = check.lag_seconds;
+        // without the chain head the lag is unknown, keep the last known level
+        if let Some(level) = check.lag_level {
+            self.lag_level = level;
+        }
+        self.indexing_rate = check.rates.map(|r| r.indexing);
+        self.chain_rate = check.rates.map(|r| r.chain);
+        self.eta_seconds = check.eta_seconds.filter(|eta| eta.is_finite());
+        self.falling_behind = check.eta_seconds.is_some_and(f64::is_infinite);
+        self.block_hash = check.block_hash;
+        self.expected_chain_id = check.expected_chain_id;
+        self.rpc_providers = check.providers;
+        let deployment_change = match (&self.deployment, &check.deployment) {
+            (Some(previous), Some(current)) if previous != current => Some(DeploymentChange {
+                target: config.name.clone(),
+                at: now,
+                from: Some(previous.clone()),
+                to: current.clone(),
+            }),
+            _ => None,
+        };
+        // keep the last known deployment while the subgraph is unreachable
+        if check.deployment.is_some() {
+            self.deployment = check.deployment;
+        }
+        self.ingestor_lag_blocks = check.ingestor_lag;
+        self.blocks_behind_graph_node = check.blocks_behind_graph_node;
+        if check.network.is_some() {
+            self.network = check.network;
+        }
+        self.pending_version = check.pending_version;
+        // relabel subgraph_info when the network or deployment changes
+        let info_labels = (
+            self.network.clone().unwrap_or_default(),
+            self.deployment.clone().unwrap_or_default(),
+        );
+        let info_change = (self.info_labels.as_ref() != Some(&info_labels))
+            .then(|| (self.info_labels.replace(info_labels.clone()), info_labels));
+        let fatal_error = check.indexing_status.as_ref().and_then(|indexing| indexing.fatal_error.clone());
+        self.indexing_status = check.indexing_status;
+        let new_fatal_error = fatal_error.clone().filter(|e| self.fatal_error.as_ref() != Some(e));
+        // keep the last known error while the index node is unreachable
+        if self.indexing_status.is_some() {
+            self.fatal_error = fatal_error;
+        }
+        self.last_checked = now.to_rfc3339();
+        // a target that is unhealthy from its first check is notified, one that starts healthy isn't
+        let notifiable = !first_check || self.state != HealthState::Healthy;
+        let transition = (notifiable && self.state != previous_state).then(|| Transition {
+            target: config.name.clone(),
+            at: now,
+            from: previous_state,
+            to: self.state,
+            reasons: self.reasons.clone(),
+        });
+        // like the state, a new lag level is only notified once fall/rise let the checks through; the
+        // level from before a restart isn't known, so the first check since startup only sets it
+        let previous_lag_level = self.notified_lag_level;
+        let settled = transition.is_some() || self.state == check.observed_state;
+        if settled || first_since_startup {
+            self.notified_lag_level = self.lag_level;
+        }
+        let lag_change = (!first_since_startup && settled && previous_lag_level != self.lag_level)
+            .then_some(previous_lag_level);
+        CheckOutcome {
+            state: self.state,
+            lag_level: self.lag_level,
+            failures: self.consecutive_failures,
+            successes: self.consecutive_successes,
+            transition,
+            lag_change,
+            first_since_startup,
+            new_fatal_error,
+            deployment_change,
+            info_change,
+        }
+    }
+}
+
+/// Network and deployment labels of `subgraph_info`
+type InfoLabels = (String, String);
+
+/// What a check found, before it is applied to the status
+struct Observation {
+    observed_state: HealthState,
+    reasons: Vec<Reason>,
+    synced_block: i64,
+    since_progress: Option<Duration>,
+    chain_head: i64,
+    since_head_advanced: Option<Duration>,
+    blocks_behind: i64,
+    lag_seconds: Option<f64>,
+    /// `None` when the lag couldn't be judged
+    lag_level: Option<LagLevel>,
+    rates: Option<SyncRates>,
+    eta_seconds: Option<f64>,
+    block_hash: Option<String>,
+    expected_chain_id: Option<u64>,
+    providers: Vec<ProviderStatus>,
+    deployment: Option<String>,
+    ingestor_lag: Option<i64>,
+    blocks_behind_graph_node: Option<i64>,
+    network: Option<String>,
+    pending_version: Option<IndexingStatus>,
+    indexing_status: Option<IndexingStatus>,
+}
+
+/// What changed with a check, to be logged, notified and persisted
+struct CheckOutcome {
+    /// Reported state after the check
+    state: HealthState,
+    lag_level: LagLevel,
+    failures: u32,
+    successes: u32,
+    transition: Option<Transition>,
+    /// Lag level notifiers were told about before, if a new one is to be notified
+    lag_change: Option<LagLevel>,
+    first_since_startup: bool,
+    /// Fatal error not seen in the previous check
+    new_fatal_error: Option<SubgraphError>,
+    deployment_change: Option<DeploymentChange>,
+    /// Previous and new `subgraph_info` labels, if they changed
+    info_change: Option<(Option<InfoLabels>, InfoLabels)>,
 }
 
 #[derive(Deserialize)]
@@ -53,12 +352,15 @@ struct MetaData {
     block: BlockData,
     #[serde(rename = "hasIndexingErrors")]
     has_indexing_errors: bool,
+    deployment: Option<String>,
 }
 
 #[derive(Deserialize)]
 struct BlockData {
     number: i64,
-//    hash: String,
+    hash: Option<String>,
+    /// Only queried with `block_timestamp = "meta"`, not every graph-node version supports it
+    timestamp: Option<i64>,
 }
 
 #[derive(Deserialize)]
@@ -66,37 +368,190 @@ struct RpcResponse {
     result: String,
 }
 
+#[derive(Deserialize)]
+struct RpcBlockResponse {
+    result: Option<RpcBlock>,
+}
+
+#[derive(Deserialize)]
+struct RpcBlock {
+    hash: String,
+    timestamp: String,
+}
+
+impl RpcBlock {
+    fn timestamp(&self) -> Option<i64> {
+        i64::from_str_radix(self.timestamp.trim_start_matches("0x"), 16).ok()
+    }
+}
+
 struct AppState {
-    subgraph_url: String,
-    rpc_url: String,
-    status: Arc<Mutex<SubgraphStatus>>,
+    targets: Vec<Arc<Target>>,
+    status_codes: StatusCodes,
+    client: Client,
+    http: HttpConfig,
     registry: Registry,
     metrics: Arc<SubgraphMetrics>,
+    store: Option<Arc<Store>>,
+    notifiers: Vec<NotifierConfig>,
+    policy: Policy,
+    alertmanager: Option<Alertmanager>,
+}
+
+struct Target {
+    config: TargetConfig,
+    /// Metric and dashboard labels of the RPC providers, in the order of `config.rpc_urls`
+    providers: Vec<String>,
+    /// Host of the subgraph endpoint, the `endpoint` label of `subgraph_info`
+    endpoint: String,
+    status: Mutex<SubgraphStatus>,
+    history: Mutex<History>,
+    /// Chain IDs of the RPC providers, in the order of `config.rpc_urls`, asked for until known
+    chain_ids: Mutex<Vec<Option<u64>>>,
+    /// When the last check completed, or the checker started
+    last_completed: Mutex<Instant>,
+    /// Whether `_meta.block.timestamp` is queried, cleared when the graph-node doesn't know the field
+    meta_timestamp: AtomicBool,
+}
+
+impl Target {
+    /// Time since the last check completed, when it exceeds `monitor_stale_intervals`
+    /// intervals nothing the status says is current
+    fn stale_for(&self) -> Option<Duration> {
+        let since = self.last_completed.lock().unwrap().elapsed();
+        let limit = Duration::from_secs(self.config.interval) * self.config.monitor_stale_intervals;
+        (since > limit).then_some(since)
+    }
+
+    /// Status as reported by `/health` and the dashboard, down if the checker is stale
+    fn current_status(&self) -> SubgraphStatus {
+        let mut status = self.status.lock().unwrap().clone();
+        if self.stale_for().is_some() {
+            status.state = HealthState::Down;
+            status.reasons.push(Reason::MonitorStale);
+        }
+        status
+    }
+}
+
+#[derive(Serialize)]
+struct TargetHealth<'a> {
+    name: &'a str,
+    #[serde(flatten)]
+    status: SubgraphStatus,
+    thresholds: &'a Thresholds,
+    /// How an `unknown` state is reported
+    startup: Startup,
+}
+
+#[derive(Serialize)]
+struct HealthResponse<'a> {
+    state: HealthState,
+    targets: Vec<TargetHealth<'a>>,
 }
 
 #[derive(Clone)]
 struct SubgraphMetrics {
-    healthy: IntGauge,
-    synced_block: IntGauge,
-    chain_head: IntGauge,
-    blocks_behind: IntGauge,
+    info: IntGaugeVec,
+    healthy: IntGaugeVec,
+    synced_block: IntGaugeVec,
+    chain_head: IntGaugeVec,
+    blocks_behind: IntGaugeVec,
+    lag_seconds: GaugeVec,
+    indexing_rate: GaugeVec,
+    chain_rate: GaugeVec,
+    sync_eta: GaugeVec,
+    state: IntGaugeVec,
+    reason: IntGaugeVec,
+    consecutive_failures: IntGaugeVec,
+    consecutive_successes: IntGaugeVec,
+    hash_mismatches: IntCounterVec,
+    seconds_since_progress: IntGaugeVec,
+    seconds_since_head_advanced: IntGaugeVec,
+    request_attempts: IntCounterVec,
+    request_retries: IntCounterVec,
+    request_timeouts: IntCounterVec,
+    request_successes: IntCounterVec,
+    request_failures: IntCounterVec,
+    request_duration: HistogramVec,
+    checks: IntCounterVec,
+    check_duration: HistogramVec,
+    missed_ticks: IntCounterVec,
+    checker_restarts: IntCounterVec,
+    seconds_since_check: GaugeVec,
+    rpc_head: IntGaugeVec,
+    rpc_latency: GaugeVec,
+    rpc_disagrees: IntGaugeVec,
+    rpc_chain_id: IntGaugeVec,
+    index_node_health: IntGaugeVec,
+    index_node_synced: IntGaugeVec,
+    fatal_error: IntGaugeVec,
+    non_fatal_errors: IntGaugeVec,
+    index_node_chain_head: IntGaugeVec,
+    earliest_block: IntGaugeVec,
+    deployment_changes: IntCounterVec,
+    pending_synced_block: IntGaugeVec,
+    ingestor_lag: IntGaugeVec,
+    blocks_behind_graph_node: IntGaugeVec,
+    notifications: IntCounterVec,
+}
+
+impl SubgraphMetrics {
+    fn request_counters(&self, target: &str, query: &str, endpoint: &str) -> RequestCounters {
+        let labels = [target, query, endpoint];
+        RequestCounters {
+            attempts: self.request_attempts.with_label_values(&labels),
+            retries: self.request_retries.with_label_values(&labels),
+            timeouts: self.request_timeouts.with_label_values(&labels),
+            successes: self.request_successes.with_label_values(&labels),
+            duration: self.request_duration.with_label_values(&labels),
+            failures: self.request_failures.clone(),
+            labels: labels.map(str::to_string),
+        }
+    }
+}
+
+impl AppState {
+    fn target(&self, name: &str) -> Option<&Arc<Target>> {
+        self.targets.iter().find(|t| t.config.name == name)
+    }
+
+    /// Runs a query with the configured retries, counting attempts under the target, query and endpoint labels
+    async fn query<T, F, Fut>(&self, target: &str, query: &str, endpoint: &str, op: F) -> Result<T, BoxError>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, BoxError>>,
+    {
+        let counters = self.metrics.request_counters(target, query, endpoint);
+        client::with_retries(&self.http, &counters, op).await.map_err(client::without_url)
+    }
 }
 
-async fn query_subgraph_status(client: &Client, url: &str) -> Result<GraphQLResponse, reqwest::Error> {
-    let query = r#"{"query": "{_meta{block{number hash}hasIndexingErrors}}"}"#;
+async fn query_subgraph_status(client: &Client, url: &str, with_timestamp: bool) -> Result<GraphQLData, BoxError> {
+    let query = if with_timestamp {
+        r#"{"query": "{_meta{block{number hash timestamp}hasIndexingErrors deployment}}"}"#
+    } else {
+        r#"{"query": "{_meta{block{number hash}hasIndexingErrors deployment}}"}"#
+    };
 
     let res = client.post(url)
         .header("Content-Type", "application/json")
         .body(query)
         .send()
         .await?
-        .json::<GraphQLResponse>()
+        .error_for_status()?
+        .json::<GraphQLResponse<GraphQLData>>()
         .await?;
 
-    Ok(res)
+    res.into_data()
+}
+
+/// Whether a failed status query was rejected for `_meta.block.timestamp`
+fn is_unknown_timestamp_field(error: &BoxError) -> bool {
+    error.downcast_ref::<client::GraphQLError>().is_some_and(|e| e.0.contains("timestamp"))
 }
 
-async fn query_chain_head(client: &Client, url: &str) -> Result<i64, Box<dyn std::error::Error>> {
+async fn query_chain_head(client: &Client, url: &str) -> Result<i64, BoxError> {
     let query = r#"{"jsonrpc":"2.0","method":"eth_blockNumber","params":[],"id":1}"#;
 
     let res = client.post(url)
@@ -104,6 +559,7 @@ async fn query_chain_head(client: &Client, url: &str) -> Result<i64, Box<dyn std
         .body(query)
         .send()
         .await?
+        .error_for_status()?
         .json::<RpcResponse>()
         .await?;
 
@@ -114,35 +570,327 @@ async fn query_chain_head(client: &Client, url: &str) -> Result<i64, Box<dyn std
     Ok(block_number)
 }
 
-async fn check_subgraph(app_state: web::Data<AppState>) {
-    let client = Client::new();
+async fn query_chain_id(client: &Client, url: &str) -> Result<u64, BoxError> {
+    let query = r#"{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":1}"#;
+
+    let res = client.post(url)
+        .header("Content-Type", "application/json")
+        .body(query)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<RpcResponse>()
+        .await?;
+
+    Ok(u64::from_str_radix(res.result.trim_start_matches("0x"), 16)?)
+}
+
+/// Returns the given block according to the RPC, or `None` if the RPC doesn't know the block
+async fn query_block(client: &Client, url: &str, number: i64) -> Result<Option<RpcBlock>, BoxError> {
+    let query = format!(
+        r#"{{"jsonrpc":"2.0","method":"eth_getBlockByNumber","params":["0x{:x}",false],"id":1}}"#,
+        number
+    );
+
+    let res = client.post(url)
+        .header("Content-Type", "application/json")
+        .body(query)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<RpcBlockResponse>()
+        .await?;
+
+    Ok(res.result)
+}
+
+/// Time startup waits for chain IDs, so slow providers don't keep `/health` unreachable
+const VERIFY_DEADLINE: Duration = Duration::from_secs(5);
+
+/// Refuses to start when an RPC provider is on another chain than the subgraph indexes,
+/// providers or index nodes that don't answer before `deadline` are verified by the checks
+async fn verify_chain_ids(client: &Client, target: &TargetConfig, deadline: time::Instant) -> Result<(), String> {
+    let expected = match (target.chain_id, &target.index_node_url, SubgraphRef::from_url(&target.subgraph_url)) {
+        (Some(id), _, _) => Some(id),
+        (None, Some(url), Some(subgraph)) => match within(deadline, index_node::query_indexing_status(client, url, &subgraph)).await {
+            Ok(indexing) => indexing
+                .as_ref()
+                .and_then(IndexingStatus::chain)
+                .and_then(|chain| network::chain_id(&chain.network)),
+            Err(e) => {
+                eprintln!("[{}] Could not resolve the subgraph's network: {}", target.name, e);
+                None
+            },
+        },
+        _ => None,
+    };
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+
+    let queries = target.rpc_urls.iter().map(|url| within(deadline, query_chain_id(client, url)));
+    let results = futures::future::join_all(queries).await;
+    for (result, provider) in results.into_iter().zip(chain_head::provider_labels(&target.rpc_urls)) {
+        match result {
+            Ok(id) if id != expected => {
+                return Err(format!(
+                    "target {:?}: RPC provider {} is on chain {}, the subgraph indexes chain {}",
+                    target.name, provider, id, expected
+                ));
+            },
+            Ok(_) => {},
+            Err(e) => eprintln!("[{}] Could not verify the chain ID of {}: {}", target.name, provider, e),
+        }
+    }
+    Ok(())
+}
+
+/// Fails a query that hasn't completed by `deadline`
+async fn within<T>(deadline: time::Instant, query: impl std::future::Future<Output = Result<T, BoxError>>) -> Result<T, BoxError> {
+    time::timeout_at(deadline, query)
+        .await
+        .unwrap_or_else(|_| Err("no answer before the startup deadline".into()))
+        .map_err(client::without_url)
+}
+
+/// Asks every RPC provider of the target for its head concurrently
+async fn query_providers(app_state: &AppState, target: &Target) -> Vec<ProviderStatus> {
+    let client = &app_state.client;
+    let config = &target.config;
+
+    let queries = config.rpc_urls.iter().zip(&target.providers).enumerate().map(|(i, (url, provider))| async move {
+        // the chain ID doesn't change, ask only until a provider answered it
+        let known_chain_id = target.chain_ids.lock().unwrap()[i];
+        let chain_id = match known_chain_id {
+            Some(id) => Some(id),
+            None => match app_state.query(&config.name, "chain_id", provider, || query_chain_id(client, url)).await {
+                Ok(id) => {
+                    target.chain_ids.lock().unwrap()[i] = Some(id);
+                    Some(id)
+                },
+                Err(e) => {
+                    eprintln!("[{}] Error getting chain ID from {}: {}", config.name, provider, e);
+                    None
+                },
+            },
+        };
+
+        let started = Instant::now();
+        let result = app_state
+            .query(&config.name, "chain_head", provider, || query_chain_head(client, url))
+            .await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        match result {
+            Ok(head) => ProviderStatus {
+                provider: provider.clone(),
+                head: Some(head),
+                latency_ms,
+                error: None,
+                disagrees: false,
+                chain_id,
+                wrong_chain: false,
+            },
+            Err(e) => {
+                eprintln!("[{}] Error getting chain head from {}: {}", config.name, provider, e);
+                ProviderStatus {
+                    provider: provider.clone(),
+                    head: None,
+                    latency_ms,
+                    error: Some(e.to_string()),
+                    disagrees: false,
+                    chain_id,
+                    wrong_chain: false,
+                }
+            },
+        }
+    });
+
+    futures::future::join_all(queries).await
+}
+
+async fn check_subgraph(app_state: web::Data<AppState>, target: Arc<Target>) {
+    let client = &app_state.client;
+    let config = &target.config;
     
     // get current time before any async operations
-    let formatted_time = chrono::Utc::now().to_rfc3339();
+    let now = chrono::Utc::now();
     
     // query subgraph status (outside of mutex lock)
-    let subgraph_result = query_subgraph_status(&client, &app_state.subgraph_url).await;
+    let started = Instant::now();
+    let with_timestamp = target.meta_timestamp.load(Ordering::Relaxed);
+    let mut subgraph_result = app_state
+        .query(&config.name, "subgraph_status", &target.endpoint, || {
+            query_subgraph_status(client, &config.subgraph_url, with_timestamp)
+        })
+        .await;
+    // older graph-nodes reject the whole query for the unknown field, use the RPC timestamp instead
+    if with_timestamp && subgraph_result.as_ref().is_err_and(is_unknown_timestamp_field) {
+        subgraph_result = app_state
+            .query(&config.name, "subgraph_status", &target.endpoint, || {
+                query_subgraph_status(client, &config.subgraph_url, false)
+            })
+            .await;
+        if subgraph_result.is_ok() {
+            eprintln!("[{}] Subgraph endpoint has no _meta.block.timestamp, using the RPC block timestamp", config.name);
+            target.meta_timestamp.store(false, Ordering::Relaxed);
+        }
+    }
+    let subgraph_latency_ms = started.elapsed().as_millis() as u64;
     
-    // only if successful, query chain head (outside of mutex lock)
-    let chain_head_result = match &subgraph_result {
-        Ok(_) => query_chain_head(&client, &app_state.rpc_url).await,
+    // query graph-node's index-node for indexing health, if configured (outside of mutex lock)
+    let subgraph_ref = SubgraphRef::from_url(&config.subgraph_url);
+    let indexing_result = match (&config.index_node_url, &subgraph_ref) {
+        (Some(url), Some(subgraph)) => Some(
+            app_state
+                .query(&config.name, "indexing_status", &chain_head::provider_label(url), || {
+                    index_node::query_indexing_status(client, url, subgraph)
+                })
+                .await,
+        ),
+        _ => None,
+    };
+    let pending_result = match (&config.index_node_url, &subgraph_ref) {
+        (Some(url), Some(SubgraphRef::Name(name))) if config.track_pending_version => Some(
+            app_state
+                .query(&config.name, "pending_version", &chain_head::provider_label(url), || {
+                    index_node::query_pending_status(client, url, name)
+                })
+                .await,
+        ),
+        _ => None,
+    };
+    
+    // the chain the RPC providers must be on, configured or from the network graph-node indexes
+    let expected_chain_id = config.chain_id.or_else(|| match &indexing_result {
+        Some(Ok(Some(indexing))) => indexing.chain().and_then(|chain| network::chain_id(&chain.network)),
+        _ => None,
+    });
+    
+    // only if successful, query chain head from all providers (outside of mutex lock)
+    let mut providers = Vec::new();
+    let chain_head_result: Result<i64, BoxError> = match &subgraph_result {
+        Ok(_) => {
+            providers = query_providers(&app_state, &target).await;
+            // heads of providers on another chain are meaningless
+            for provider in &mut providers {
+                provider.wrong_chain = matches!(
+                    (provider.chain_id, expected_chain_id),
+                    (Some(id), Some(expected)) if id != expected
+                );
+            }
+            let heads: Vec<i64> = providers.iter().filter(|p| !p.wrong_chain).filter_map(|p| p.head).collect();
+            config.chain_head.select(&heads).ok_or_else(|| {
+                format!("{} of {} RPC providers answered", heads.len(), providers.len()).into()
+            })
+        },
         Err(_) => Err("Skipping chain head query due to subgraph error".into()),
     };
     
+    // flag providers too far from the selected head
+    if let Ok(head) = chain_head_result {
+        for provider in &mut providers {
+            provider.disagrees = provider
+                .head
+                .is_some_and(|h| (h - head).abs() > config.chain_head.tolerance);
+        }
+    }
+    
+    // track when the synced block and chain head last advanced and how fast they move
+    // (short-lived lock, no awaits inside)
+    let (since_progress, since_head_advanced, rates) = {
+        let now = Instant::now();
+        let mut status = target.status.lock().unwrap();
+        let rates = match (&subgraph_result, &chain_head_result) {
+            (Ok(response), Ok(head)) => status.rates.observe(
+                response._meta.block.number,
+                *head,
+                now,
+                Duration::from_secs(config.rate_window),
+            ),
+            _ => None,
+        };
+        (
+            subgraph_result
+                .as_ref()
+                .ok()
+                .map(|response| status.synced_progress.observe(response._meta.block.number, now)),
+            chain_head_result
+                .as_ref()
+                .ok()
+                .map(|&head| status.chain_head_progress.observe(head, now)),
+            rates,
+        )
+    };
+    
+    // fetch the synced block from a provider that agrees with the head, to verify its hash
+    // and to learn its timestamp if the subgraph didn't report it (outside of mutex lock)
+    let block_rpc_url = providers
+        .iter()
+        .position(|p| p.head.is_some() && !p.disagrees && !p.wrong_chain)
+        .map(|i| (config.rpc_urls[i].as_str(), target.providers[i].as_str()));
+    let rpc_block_result = match (&subgraph_result, &chain_head_result, block_rpc_url) {
+        (Ok(response), Ok(_), Some((rpc_url, provider)))
+            if config.verify_block_hash || response._meta.block.timestamp.is_none() =>
+        {
+            let number = response._meta.block.number;
+            Some(
+                app_state
+                    .query(&config.name, "block", provider, || query_block(client, rpc_url, number))
+                    .await,
+            )
+        },
+        _ => None,
+    };
+    let mut errors = Vec::new();
+    let rpc_block = match rpc_block_result {
+        Some(Ok(block)) => block,
+        Some(Err(e)) => {
+            eprintln!("[{}] Error getting synced block from RPC: {}", config.name, e);
+            errors.push(format!("synced block: {}", e));
+            None
+        },
+        None => None,
+    };
+    
     // process results and update state (no awaits from this point)
-    let mut is_healthy = false;
+    let rpc_head = chain_head_result.as_ref().ok().copied();
+    let mut reasons = Vec::new();
     let mut synced_block = 0;
     let mut chain_head = 0;
     let mut blocks_behind = 0;
+    let mut lag_seconds = None;
+    let mut lag_level = None;
+    let mut hash_mismatch = None;
+    let mut eta_seconds = None;
+    let mut block_hash = None;
+    let mut deployment = None;
     
     // parse results outside the lock
     match subgraph_result {
         Ok(response) => {
-            let meta = &response.data._meta;
+            let meta = &response._meta;
             synced_block = meta.block.number;
+            block_hash = meta.block.hash.clone();
+            deployment = meta.deployment.clone();
+            
+            // the age of the synced block is the lag in seconds
+            let synced_timestamp = meta
+                .block
+                .timestamp
+                .or_else(|| rpc_block.as_ref().and_then(RpcBlock::timestamp));
+            lag_seconds = synced_timestamp.map(|ts| (now.timestamp() - ts).max(0) as f64);
             
             // check if the subgraph has indexing errors
-            let has_indexing_errors = meta.has_indexing_errors;
+            if meta.has_indexing_errors {
+                reasons.push(Reason::IndexingErrors);
+            }
+            
+            // a halted chain is not the subgraph's fault, that case is reported as rpc_stale below
+            let head_stale = since_head_advanced.is_some_and(|d| d.as_secs() >= config.chain_head.stale_after);
+            if !head_stale && since_progress.is_some_and(|d| d.as_secs() >= config.stall_after) {
+                reasons.push(Reason::Stalled);
+            }
             
             // process chain head result
             match chain_head_result {
@@ -150,227 +898,786 @@ async fn check_subgraph(app_state: web::Data<AppState>) {
                     chain_head = head;
                     blocks_behind = chain_head - synced_block;
                     
-                    // determine health: no indexing errors and not too far behind
-                    is_healthy = !has_indexing_errors && blocks_behind <= 20;
+                    // a frozen RPC makes any subgraph look synced
+                    if head_stale {
+                        reasons.push(Reason::RpcStale);
+                    }
+                    // the RPC can't be behind an index built from the same chain, but `median` and
+                    // `quorum` select a head below the fastest provider, so allow the same tolerance
+                    if -blocks_behind > config.chain_head.tolerance {
+                        reasons.push(Reason::NegativeLag);
+                    }
+                    
+                    // compare the subgraph's block hash with the RPC's, a mismatch means a forked or wrong chain
+                    if let (Some(subgraph_hash), Some(rpc_block)) = (&block_hash, &rpc_block) {
+                        if config.verify_block_hash {
+                            let mismatch = !subgraph_hash.eq_ignore_ascii_case(&rpc_block.hash);
+                            if mismatch {
+                                eprintln!(
+                                    "[{}] Block hash mismatch at block {}: subgraph={}, rpc={}",
+                                    config.name, synced_block, subgraph_hash, rpc_block.hash
+                                );
+                            }
+                            hash_mismatch = Some(mismatch);
+                        }
+                    }
+                    
+                    // fall back to estimating the lag in seconds from the block time
+                    let thresholds = &config.thresholds;
+                    lag_seconds = lag_seconds.or_else(|| thresholds.lag_seconds(blocks_behind));
+                    lag_level = Some(thresholds.level(blocks_behind, lag_seconds));
                     
-                    println!(
-                        "Subgraph check: Healthy={}, Synced block={}, Chain head={}, Blocks behind={}",
-                        is_healthy, synced_block, chain_head, blocks_behind
-                    );
+                    eta_seconds = rates.map(|r| r.eta_seconds(blocks_behind));
                 },
                 Err(e) => {
-                    eprintln!("Error getting chain head: {}", e);
-                    // if we can't get chain head, rely only on indexing errors
-                    is_healthy = !has_indexing_errors;
+                    eprintln!("[{}] Error getting chain head: {}", config.name, e);
+                    errors.push(format!("chain head: {}", e));
+                    // if we can't get chain head, the lag in blocks is unknown, but the block
+                    // timestamp from the subgraph still tells the lag in seconds
+                    reasons.push(Reason::RpcUnreachable);
+                    lag_level = lag_seconds.and_then(|lag| config.thresholds.level_in_seconds(lag));
                 }
             }
+            match lag_level {
+                Some(LagLevel::Warn) => reasons.push(Reason::Lagging),
+                Some(LagLevel::Critical) => reasons.push(Reason::LagCritical),
+                _ => {},
+            }
         },
         Err(e) => {
-            eprintln!("Error querying subgraph: {}", e);
+            eprintln!("[{}] Error querying subgraph: {}", config.name, e);
+            errors.push(format!("subgraph: {}", e));
+            reasons.push(Reason::SubgraphUnreachable);
         }
     }
     
-    // now update metrics and state with a short-lived lock
-    {
-        // update status with mutex lock (no awaits inside this block)
-        let mut status = app_state.status.lock().unwrap();
-        status.healthy = is_healthy;
-        status.synced_block_height = synced_block;
-        status.chain_head_block_height = chain_head;
-        status.blocks_behind = blocks_behind;
-        status.last_checked = formatted_time;
+    // a mismatch at the chain tip is usually a reorg graph-node hasn't reverted yet, only one
+    // that persists for `hash_mismatch_checks` checks is reported (short-lived lock)
+    if let Some(mismatch) = hash_mismatch {
+        let mut status = target.status.lock().unwrap();
+        status.consecutive_hash_mismatches = if mismatch { status.consecutive_hash_mismatches + 1 } else { 0 };
+        if status.consecutive_hash_mismatches >= config.hash_mismatch_checks {
+            reasons.push(Reason::BlockHashMismatch);
+        }
     }
     
-    // update metrics (outside lock)
-    app_state.metrics.healthy.set(if is_healthy { 1 } else { 0 });
-    app_state.metrics.synced_block.set(synced_block);
-    app_state.metrics.chain_head.set(chain_head);
-    app_state.metrics.blocks_behind.set(blocks_behind);
-}
-
-#[get("/")]
-async fn root(app_state: web::Data<AppState>) -> impl Responder {
-    // minimize mutex lock duration by cloning only what's needed
-    let status = {
-        let status_guard = app_state.status.lock().unwrap();
-        status_guard.clone()
+    // graph-node's view of indexing health, independent of the query endpoint
+    let indexing_status = match indexing_result {
+        Some(Ok(Some(indexing))) => {
+            match indexing.health {
+                SubgraphHealth::Healthy => {},
+                SubgraphHealth::Unhealthy => reasons.push(Reason::SubgraphUnhealthy),
+                SubgraphHealth::Failed => reasons.push(Reason::SubgraphFailed),
+            }
+            Some(indexing)
+        },
+        Some(Ok(None)) => {
+            eprintln!("[{}] Subgraph not found on index node", config.name);
+            errors.push("index node: subgraph not found".to_string());
+            reasons.push(Reason::IndexNodeUnreachable);
+            None
+        },
+        Some(Err(e)) => {
+            eprintln!("[{}] Error querying index node: {}", config.name, e);
+            errors.push(format!("index node: {}", e));
+            reasons.push(Reason::IndexNodeUnreachable);
+            None
+        },
+        None => None,
     };
-
-    let health_color = if status.healthy { "#22c55e" } else { "#ef4444" };
-    let health_text = if status.healthy { "Healthy" } else { "Unhealthy" };
     
-    HttpResponse::Ok().content_type("text/html").body(format!(
-        r#"<!DOCTYPE html>
-<html>
-<head>
-    <meta charset="utf-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1">
-    <title>Subgraph Monitor</title>
-    <style>
-        :root {{
-            --bg-color: #f8fafc;
-            --card-bg: #ffffff;
-            --text-color: #1e293b;
-            --border-color: #e2e8f0;
-            --accent-color: #3b82f6;
-        }}
-        body {{
-            font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, Oxygen, Ubuntu, Cantarell, sans-serif;
-            background-color: var(--bg-color);
-            color: var(--text-color);
-            margin: 0;
-            padding: 0;
-            line-height: 1.5;
-        }}
-        .container {{
-            max-width: 800px;
-            margin: 0 auto;
-            padding: 2rem;
-        }}
-        .card {{
-            background-color: var(--card-bg);
-            border-radius: 8px;
-            box-shadow: 0 4px 6px rgba(0, 0, 0, 0.05);
-            padding: 1.5rem;
-            margin-bottom: 1.5rem;
-        }}
-        h1 {{
-            margin: 0 0 1.5rem 0;
-            font-weight: 600;
-            font-size: 1.8rem;
-            border-bottom: 2px solid var(--accent-color);
-            padding-bottom: 0.5rem;
-            color: var(--accent-color);
-        }}
-        p {{
-            margin: 0.75rem 0;
-        }}
-        .info-grid {{
-            display: grid;
-            grid-template-columns: repeat(auto-fill, minmax(300px, 1fr));
-            gap: 1rem;
-            margin: 1.5rem 0;
-        }}
-        .info-item {{
-            padding: 1rem;
-            background-color: #f1f5f9;
-            border-radius: 6px;
-            border-left: 4px solid var(--accent-color);
-        }}
-        .status-indicator {{
-            font-weight: 600;
-            padding: 0.25rem 0.75rem;
-            border-radius: 9999px;
-            background-color: {health_color};
-            color: white;
-            display: inline-block;
-        }}
-        .stat-label {{
-            font-size: 0.875rem;
-            color: #64748b;
-            margin-bottom: 0.25rem;
-        }}
-        .stat-value {{
-            font-size: 1.25rem;
-            font-weight: 600;
-        }}
-        .metrics-links {{
-            margin-top: 1.5rem;
-            text-align: center;
-        }}
-        .metrics-links a {{
-            display: inline-block;
-            margin: 0 0.5rem;
-            padding: 0.5rem 1rem;
-            background-color: var(--accent-color);
-            color: white;
-            text-decoration: none;
-            border-radius: 4px;
-            font-weight: 500;
-            transition: background-color 0.2s;
-        }}
-        .metrics-links a:hover {{
-            background-color: #2563eb;
-        }}
-        .timestamp {{
-            font-size: 0.875rem;
-            color: #64748b;
-            text-align: right;
-            margin-top: 0.5rem;
-        }}
-    </style>
-</head>
-<body>
-    <div class="container">
-        <div class="card">
-            <h1>Subgraph Monitor</h1>
-            
-            <div class="info-grid">
-                <div class="info-item">
-                    <div class="stat-label">Subgraph</div>
-                    <div class="stat-value" style="font-size: 0.9rem; word-break: break-all;">{}</div>
-                </div>
-                <div class="info-item">
-                    <div class="stat-label">RPC Endpoint</div>
-                    <div class="stat-value" style="font-size: 0.9rem; word-break: break-all;">{}</div>
-                </div>
-            </div>
-            
-            <div class="card">
-                <div style="display: flex; align-items: center; justify-content: space-between; margin-bottom: 1rem;">
-                    <span style="font-weight: 600; font-size: 1.2rem;">Status:</span>
-                    <span class="status-indicator">{}</span>
-                </div>
-                
-                <div class="info-grid">
-                    <div class="info-item">
-                        <div class="stat-label">Synced Block</div>
-                        <div class="stat-value">{}</div>
-                    </div>
-                    <div class="info-item">
-                        <div class="stat-label">Chain Head</div>
-                        <div class="stat-value">{}</div>
-                    </div>
-                    <div class="info-item">
-                        <div class="stat-label">Blocks Behind</div>
-                        <div class="stat-value">{}</div>
-                    </div>
-                </div>
-                
-                <div class="timestamp">Last checked: {}</div>
-            </div>
-            
-            <div class="metrics-links">
-                <a href="/health">JSON Health Endpoint</a>
-                <a href="/metrics">Prometheus Metrics</a>
-            </div>
-        </div>
-    </div>
-</body>
-</html>"#,
-        app_state.subgraph_url,
-        app_state.rpc_url,
-        health_text,
-        status.synced_block_height,
-        status.chain_head_block_height,
-        status.blocks_behind,
-        status.last_checked
-    ))
+    if providers.iter().any(|p| p.wrong_chain) {
+        reasons.push(Reason::ChainMismatch);
+        errors.push(format!("RPC providers not on chain {}", expected_chain_id.unwrap_or_default()));
+    }
+    
+    // graph-node's own chain head against the RPC's, tells a lagging block ingestor from a slow subgraph
+    let graph_node_head = indexing_status
+        .as_ref()
+        .and_then(IndexingStatus::chain)
+        .and_then(|chain| chain.chain_head_block.as_ref())
+        .map(|block| block.number);
+    let ingestor_lag = rpc_head.zip(graph_node_head).map(|(rpc, graph_node)| rpc - graph_node);
+    if ingestor_lag.is_some_and(|lag| lag > config.thresholds.ingestor_blocks()) {
+        reasons.push(Reason::IngestorLagging);
+    }
+    let blocks_behind_graph_node = graph_node_head.filter(|_| synced_block > 0).map(|head| head - synced_block);
+    
+    let network = indexing_status
+        .as_ref()
+        .and_then(IndexingStatus::chain)
+        .map(|chain| chain.network.clone())
+        .or_else(|| config.chain_id.and_then(network::name).map(str::to_string));
+    
+    let pending_version = match pending_result {
+        Some(Ok(pending)) => pending,
+        Some(Err(e)) => {
+            eprintln!("[{}] Error querying pending version: {}", config.name, e);
+            errors.push(format!("pending version: {}", e));
+            None
+        },
+        None => None,
+    };
+    
+    let observed_state = health::state_for(&reasons);
+    app_state.metrics.checks.with_label_values(&[&config.name, observed_state.as_str()]).inc();
+    
+    // update metrics before the state takes ownership of the reasons
+    let labels = [config.name.as_str()];
+    let metrics = &app_state.metrics;
+    metrics.synced_block.with_label_values(&labels).set(synced_block);
+    metrics.chain_head.with_label_values(&labels).set(chain_head);
+    metrics.blocks_behind.with_label_values(&labels).set(blocks_behind);
+    if let Some(lag) = lag_seconds {
+        metrics.lag_seconds.with_label_values(&labels).set(lag);
+    }
+    if let Some(rates) = rates {
+        metrics.indexing_rate.with_label_values(&labels).set(rates.indexing);
+        metrics.chain_rate.with_label_values(&labels).set(rates.chain);
+    }
+    if let Some(eta) = eta_seconds {
+        // +Inf while falling further behind
+        metrics.sync_eta.with_label_values(&labels).set(eta);
+    }
+    if let Some(since) = since_progress {
+        metrics.seconds_since_progress.with_label_values(&labels).set(since.as_secs() as i64);
+    }
+    if let Some(since) = since_head_advanced {
+        metrics.seconds_since_head_advanced.with_label_values(&labels).set(since.as_secs() as i64);
+    }
+    for provider in &providers {
+        let provider_labels = [config.name.as_str(), provider.provider.as_str()];
+        metrics.rpc_head.with_label_values(&provider_labels).set(provider.head.unwrap_or(0));
+        metrics.rpc_latency.with_label_values(&provider_labels).set(provider.latency_ms as f64 / 1000.0);
+        metrics.rpc_disagrees.with_label_values(&provider_labels).set(if provider.disagrees { 1 } else { 0 });
+        if let Some(chain_id) = provider.chain_id {
+            metrics.rpc_chain_id.with_label_values(&provider_labels).set(chain_id as i64);
+        }
+    }
+    if let Some(indexing) = &indexing_status {
+        for h in SubgraphHealth::ALL {
+            metrics.index_node_health.with_label_values(&[&config.name, h.as_str()]).set(if h == indexing.health { 1 } else { 0 });
+        }
+        metrics.index_node_synced.with_label_values(&labels).set(if indexing.synced { 1 } else { 0 });
+        metrics.fatal_error.with_label_values(&labels).set(if indexing.fatal_error.is_some() { 1 } else { 0 });
+        metrics.non_fatal_errors.with_label_values(&labels).set(indexing.non_fatal_errors.len() as i64);
+        if let Some(chain) = indexing.chain() {
+            if let Some(head) = &chain.chain_head_block {
+                metrics.index_node_chain_head.with_label_values(&labels).set(head.number);
+            }
+            if let Some(earliest) = &chain.earliest_block {
+                metrics.earliest_block.with_label_values(&labels).set(earliest.number);
+            }
+        }
+    }
+    if let Some(lag) = ingestor_lag {
+        metrics.ingestor_lag.with_label_values(&labels).set(lag);
+    }
+    if let Some(behind) = blocks_behind_graph_node {
+        metrics.blocks_behind_graph_node.with_label_values(&labels).set(behind);
+    }
+    if config.track_pending_version {
+        // 0 while no new version is syncing
+        let pending_block = pending_version
+            .as_ref()
+            .and_then(IndexingStatus::chain)
+            .and_then(|chain| chain.latest_block.as_ref())
+            .map_or(0, |block| block.number);
+        metrics.pending_synced_block.with_label_values(&labels).set(pending_block);
+    }
+    for r in Reason::ALL {
+        metrics.reason.with_label_values(&[&config.name, r.as_str()]).set(if reasons.contains(&r) { 1 } else { 0 });
+    }
+    if hash_mismatch == Some(true) {
+        metrics.hash_mismatches.with_label_values(&labels).inc();
+    }
+    
+    let rpc_latency_ms = providers.iter().map(|p| p.latency_ms).max();
+    let mut record = CheckRecord {
+        checked_at: now,
+        state: observed_state,
+        observed_state,
+        reasons: reasons.clone(),
+        synced_block_height: synced_block,
+        chain_head_block_height: chain_head,
+        blocks_behind,
+        lag_seconds,
+        deployment: deployment.clone(),
+        subgraph_latency_ms,
+        rpc_latency_ms,
+        errors,
+    };
+    
+    // now update state with a short-lived lock
+    let CheckOutcome {
+        state,
+        lag_level,
+        failures,
+        successes,
+        transition,
+        lag_change,
+        first_since_startup,
+        new_fatal_error,
+        deployment_change,
+        info_change,
+    } = target.status.lock().unwrap().apply(config, now, Observation {
+        observed_state,
+        reasons,
+        synced_block,
+        since_progress,
+        chain_head,
+        since_head_advanced,
+        blocks_behind,
+        lag_seconds,
+        lag_level,
+        rates,
+        eta_seconds,
+        block_hash,
+        expected_chain_id,
+        providers,
+        deployment,
+        ingestor_lag,
+        blocks_behind_graph_node,
+        network,
+        pending_version,
+        indexing_status,
+    });
+    
+    if let Some(change) = &deployment_change {
+        println!(
+            "[{}] Deployment changed: {} -> {}",
+            config.name, change.from.as_deref().unwrap_or("-"), change.to
+        );
+        metrics.deployment_changes.with_label_values(&labels).inc();
+    }
+    
+    // log each distinct fatal error once, not on every check
+    if let Some(error) = &new_fatal_error {
+        eprintln!("[{}] Fatal indexing error at {}", config.name, error);
+    }
+    
+    if let Some(transition) = &transition {
+        println!(
+            "[{}] State changed: {} -> {}",
+            config.name, transition.from.as_str(), transition.to.as_str()
+        );
+    }
+    
+    // lag thresholds crossed without a change of the reported state are notified on their own
+    let event = match (&transition, lag_change) {
+        (Some(transition), previous_lag_level) => Some((
+            Event::transition_kind(transition.to),
+            transition.from,
+            previous_lag_level.unwrap_or(lag_level),
+        )),
+        (None, Some(previous_lag_level)) => Some((EventKind::Lag, state, previous_lag_level)),
+        (None, None) => None,
+    };
+    if let Some((kind, from, previous_lag_level)) = event {
+        notify(&app_state, Event {
+            kind,
+            target: config.name.clone(),
+            at: now,
+            from,
+            to: state,
+            reasons: record.reasons.clone(),
+            previous_lag_level,
+            lag_level,
+            blocks_behind,
+            lag_seconds,
+            synced_block,
+            chain_head,
+            deployment: record.deployment.clone(),
+        });
+    }
+    
+    // an alert loaded from the store whose target recovered while the monitor was down
+    if first_since_startup {
+        if let Some(alert) = app_state.policy.reconcile(&config.name, state) {
+            println!("[{}] Closing the alert opened at {}, recovered while stopped", config.name, alert.opened_at);
+            persist_alert(&app_state, AlertChange::Closed(alert.target.clone()));
+            notify(&app_state, Event {
+                kind: EventKind::Recovered,
+                target: config.name.clone(),
+                at: now,
+                from: alert.state,
+                to: state,
+                reasons: Vec::new(),
+                previous_lag_level: lag_level,
+                lag_level,
+                blocks_behind,
+                lag_seconds,
+                synced_block,
+                chain_head,
+                deployment: record.deployment.clone(),
+            });
+        }
+    }
+    
+    // push to Alertmanager right away instead of waiting for a scrape and rule evaluation
+    if let Some(alertmanager) = &app_state.alertmanager {
+        let description = format!(
+            "synced block {}, chain head {}, {} blocks behind",
+            synced_block, chain_head, blocks_behind
+        );
+        if let Some(update) = alertmanager.update(&config.name, config.interval, state, &record.reasons, &description, now) {
+            push_alerts(&app_state, &config.name, update);
+        }
+    }
+    
+    // keep the check in the history with the reported state
+    record.state = state;
+    if let Some(store) = app_state.store.clone() {
+        let name = config.name.clone();
+        let record = record.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            store.insert_check(&name, &record, transition.as_ref())?;
+            deployment_change.map_or(Ok(()), |change| store.insert_deployment_change(&change))
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {},
+            Ok(Err(e)) => eprintln!("[{}] Error persisting check: {}", config.name, e),
+            Err(e) => eprintln!("[{}] Error persisting check: {}", config.name, e),
+        }
+    }
+    target.history.lock().unwrap().push(record);
+    
+    println!(
+        "[{}] Subgraph check: State={}, Observed={}, Synced block={}, Chain head={}, Blocks behind={}",
+        config.name, state.as_str(), observed_state.as_str(), synced_block, chain_head, blocks_behind
+    );
+    
+    // update state dependent metrics (outside lock)
+    // degraded targets still serve, so they count as healthy here
+    metrics.healthy.with_label_values(&labels).set(if state == HealthState::Down { 0 } else { 1 });
+    for s in HealthState::ALL {
+        metrics.state.with_label_values(&[&config.name, s.as_str()]).set(if s == state { 1 } else { 0 });
+    }
+    if let Some((previous, (network, deployment))) = info_change {
+        // drop the old label set, or the previous deployment would be reported forever
+        if let Some((network, deployment)) = previous {
+            let _ = metrics.info.remove_label_values(&[&config.name, &network, &deployment, &target.endpoint]);
+        }
+        metrics.info.with_label_values(&[&config.name, &network, &deployment, &target.endpoint]).set(1);
+    }
+    metrics.consecutive_failures.with_label_values(&labels).set(failures.into());
+    metrics.consecutive_successes.with_label_values(&labels).set(successes.into());
+}
+
+/// Routes an event of a state change through the notification policy and sends it to
+/// the notifiers it is due for
+fn notify(app_state: &web::Data<AppState>, event: Event) {
+    let routes = app_state.policy.route(&app_state.notifiers, &event);
+    if let Some(change) = app_state.policy.track(&event) {
+        persist_alert(app_state, change);
+    }
+
+    let event = Arc::new(event);
+    for (index, outcome) in routes {
+        if outcome == Outcome::Send {
+            deliver(app_state, index, event.clone());
+        } else {
+            let notifier = &app_state.notifiers[index].name;
+            app_state.metrics.notifications.with_label_values(&[&event.target, notifier, outcome.as_str()]).inc();
+        }
+    }
+}
+
+/// Sends an event to a notifier in the background, so a slow sink doesn't delay the checks
+fn deliver(app_state: &web::Data<AppState>, index: usize, event: Arc<Event>) {
+    let app_state = app_state.clone();
+    tokio::spawn(async move {
+        let notifier = &app_state.notifiers[index];
+        let result = app_state
+            .query(&event.target, "notify", &notifier.name, || notifier.send(&app_state.client, &event))
+            .await;
+        let outcome = match result {
+            Ok(()) => "sent",
+            Err(e) => {
+                eprintln!("[{}] Error notifying {}: {}", event.target, notifier.name, e);
+                "failed"
+            },
+        };
+        app_state.metrics.notifications.with_label_values(&[&event.target, &notifier.name, outcome]).inc();
+    });
+}
+
+/// Posts alerts to Alertmanager in the background, so a slow Alertmanager doesn't delay the checks
+fn push_alerts(app_state: &web::Data<AppState>, target: &str, update: Update) {
+    let app_state = app_state.clone();
+    let target = target.to_string();
+    tokio::spawn(async move {
+        let Some(alertmanager) = &app_state.alertmanager else {
+            return;
+        };
+        let result = app_state
+            .query(&target, "alertmanager", &alertmanager.endpoint, || alertmanager.post(&app_state.client, &update))
+            .await;
+        match result {
+            Ok(()) => alertmanager.commit(update),
+            Err(e) => eprintln!("[{}] Error pushing alerts to Alertmanager: {}", target, e),
+        }
+    });
+}
+
+fn persist_alert(app_state: &AppState, change: AlertChange) {
+    let Some(store) = app_state.store.clone() else {
+        return;
+    };
+    tokio::task::spawn_blocking(move || {
+        let (target, result) = match &change {
+            AlertChange::Updated(alert) => (&alert.target, store.upsert_alert(alert)),
+            AlertChange::Closed(target) => (target, store.delete_alert(target)),
+        };
+        if let Err(e) = result {
+            eprintln!("[{}] Error persisting alert: {}", target, e);
+        }
+    });
+}
+
+/// Event for the open alert of a target, with its current status
+fn alert_event(target: &Target, kind: EventKind, alert: &Alert) -> Event {
+    let status = target.current_status();
+    Event {
+        kind,
+        target: alert.target.clone(),
+        at: chrono::Utc::now(),
+        from: alert.state,
+        to: alert.state,
+        reasons: alert.reasons.clone(),
+        previous_lag_level: status.lag_level,
+        lag_level: status.lag_level,
+        blocks_behind: status.blocks_behind,
+        lag_seconds: status.lag_seconds,
+        synced_block: status.synced_block_height,
+        chain_head: status.chain_head_block_height,
+        deployment: status.deployment,
+    }
+}
+
+/// Sends delayed events, reminders and escalations as they fall due
+async fn run_notify_policy(app_state: web::Data<AppState>) {
+    let mut interval = time::interval(Duration::from_secs(10));
+    loop {
+        interval.tick().await;
+        for due in app_state.policy.due(&app_state.notifiers, chrono::Utc::now()) {
+            let (index, kind, alert) = match due {
+                Due::Delayed(index, event) => {
+                    deliver(&app_state, index, Arc::new(event));
+                    continue;
+                },
+                Due::Reminder(index, alert) => (index, EventKind::Reminder, alert),
+                Due::Escalation(index, alert) => {
+                    println!("[{}] Escalating to {}", alert.target, app_state.notifiers[index].name);
+                    persist_alert(&app_state, AlertChange::Updated(alert.clone()));
+                    (index, EventKind::Escalation, alert)
+                },
+            };
+            let Some(target) = app_state.target(&alert.target) else {
+                continue;
+            };
+            let event = alert_event(target, kind, &alert);
+            deliver(&app_state, index, Arc::new(event));
+        }
+    }
 }
 
+/// Aggregate health, the worst state across all targets decides the status code,
+/// targets without a completed check count according to their startup mode
 #[get("/health")]
 async fn health_endpoint(app_state: web::Data<AppState>) -> impl Responder {
-    let status = app_state.status.lock().unwrap().clone();
+    let targets: Vec<TargetHealth> = app_state
+        .targets
+        .iter()
+        .map(|t| TargetHealth {
+            name: &t.config.name,
+            status: t.current_status(),
+            thresholds: &t.config.thresholds,
+            startup: t.config.startup,
+        })
+        .collect();
 
-    let status_code = if status.healthy { 200 } else { 503 };
+    let state = targets
+        .iter()
+        .map(|t| t.startup.resolve(t.status.state))
+        .max()
+        .unwrap_or(HealthState::Healthy);
+    let status_code = app_state.status_codes.for_state(state);
 
     HttpResponse::build(actix_web::http::StatusCode::from_u16(status_code).unwrap())
         .content_type("application/json")
-        .json(status)
+        .json(HealthResponse { state, targets })
+}
+
+#[get("/health/{name}")]
+async fn target_health_endpoint(app_state: web::Data<AppState>, name: web::Path<String>) -> impl Responder {
+    let Some(target) = app_state.target(&name) else {
+        return HttpResponse::NotFound().body(format!("unknown target: {}", name));
+    };
+    let status = target.current_status();
+
+    let status_code = app_state.status_codes.for_state(target.config.startup.resolve(status.state));
+
+    HttpResponse::build(actix_web::http::StatusCode::from_u16(status_code).unwrap())
+        .content_type("application/json")
+        .json(TargetHealth {
+            name: &target.config.name,
+            status,
+            thresholds: &target.config.thresholds,
+            startup: target.config.startup,
+        })
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// Only this target, all targets if unset
+    target: Option<String>,
+    /// RFC 3339 or unix seconds
+    from: Option<String>,
+    /// RFC 3339 or unix seconds
+    to: Option<String>,
+    /// Most recent checks returned per target
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct TargetHistory<'a> {
+    name: &'a str,
+    checks: Vec<CheckRecord>,
+}
+
+fn parse_time(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    match value.parse::<i64>() {
+        Ok(seconds) => chrono::DateTime::from_timestamp(seconds, 0),
+        Err(_) => chrono::DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|t| t.with_timezone(&chrono::Utc)),
+    }
+}
+
+type TimeRange = (Option<chrono::DateTime<chrono::Utc>>, Option<chrono::DateTime<chrono::Utc>>);
+
+/// Parses the `from` / `to` query parameters, the error message if either is invalid
+fn parse_range(from: &Option<String>, to: &Option<String>) -> Result<TimeRange, String> {
+    let mut range = [None, None];
+    for (bound, value) in range.iter_mut().zip([from, to]) {
+        if let Some(value) = value {
+            match parse_time(value) {
+                Some(time) => *bound = Some(time),
+                None => return Err(format!("invalid time: {}", value)),
+            }
+        }
+    }
+    let [from, to] = range;
+    Ok((from, to))
+}
+
+#[get("/history")]
+async fn history_endpoint(app_state: web::Data<AppState>, query: web::Query<HistoryQuery>) -> impl Responder {
+    let (from, to) = match parse_range(&query.from, &query.to) {
+        Ok(range) => range,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let limit = query.limit.unwrap_or(100);
+
+    let targets: Vec<&Arc<Target>> = match &query.target {
+        Some(name) => match app_state.target(name) {
+            Some(target) => vec![target],
+            None => return HttpResponse::NotFound().body(format!("unknown target: {}", name)),
+        },
+        None => app_state.targets.iter().collect(),
+    };
+
+    let history: Vec<TargetHistory> = targets
+        .into_iter()
+        .map(|t| TargetHistory {
+            name: &t.config.name,
+            checks: t.history.lock().unwrap().query(from, to, limit),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(history)
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    /// Only this target, all targets if unset
+    target: Option<String>,
+    /// RFC 3339 or unix seconds
+    from: Option<String>,
+    /// RFC 3339 or unix seconds
+    to: Option<String>,
+    /// Most recent events returned
+    limit: Option<usize>,
+}
+
+/// Reads events from the store for `/incidents` and `/deployments`, newest first
+async fn stored_events<T, F>(app_state: &AppState, query: &EventsQuery, read: F) -> HttpResponse
+where
+    T: Serialize + Send + 'static,
+    F: FnOnce(&Store, Option<&str>, TimeRange, usize) -> rusqlite::Result<Vec<T>> + Send + 'static,
+{
+    let Some(store) = app_state.store.clone() else {
+        return HttpResponse::NotFound().body("storage not enabled");
+    };
+    let range = match parse_range(&query.from, &query.to) {
+        Ok(range) => range,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let limit = query.limit.unwrap_or(100);
+    if let Some(name) = &query.target {
+        if app_state.target(name).is_none() {
+            return HttpResponse::NotFound().body(format!("unknown target: {}", name));
+        }
+    }
+
+    let target = query.target.clone();
+    let result = web::block(move || read(&store, target.as_deref(), range, limit)).await;
+    match result {
+        Ok(Ok(events)) => HttpResponse::Ok().json(events),
+        Ok(Err(e)) => HttpResponse::InternalServerError().body(e.to_string()),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Health state transitions
+#[get("/incidents")]
+async fn incidents_endpoint(app_state: web::Data<AppState>, query: web::Query<EventsQuery>) -> impl Responder {
+    stored_events(&app_state, &query, |store, target, (from, to), limit| {
+        store.transitions(target, from, to, limit)
+    })
+    .await
+}
+
+/// Changes of the deployment served by the subgraph endpoints
+#[get("/deployments")]
+async fn deployments_endpoint(app_state: web::Data<AppState>, query: web::Query<EventsQuery>) -> impl Responder {
+    stored_events(&app_state, &query, |store, target, (from, to), limit| {
+        store.deployment_changes(target, from, to, limit)
+    })
+    .await
+}
+
+/// Open alerts of unhealthy targets and whether they are acknowledged
+#[get("/alerts")]
+async fn alerts_endpoint(app_state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(app_state.policy.alerts())
+}
+
+#[derive(Deserialize)]
+struct AckQuery {
+    /// Who acknowledged the alert
+    by: Option<String>,
+}
+
+/// Acknowledges the alert of a target, stopping its reminders and escalation until it recovers
+#[post("/alerts/{name}/ack")]
+async fn ack_endpoint(
+    app_state: web::Data<AppState>,
+    name: web::Path<String>,
+    query: web::Query<AckQuery>,
+) -> impl Responder {
+    let by = query.by.as_deref().unwrap_or("unknown");
+    acknowledged(&app_state, app_state.policy.acknowledge(&name, by, chrono::Utc::now()), &name)
+}
+
+#[post("/alerts/{name}/unack")]
+async fn unack_endpoint(app_state: web::Data<AppState>, name: web::Path<String>) -> impl Responder {
+    acknowledged(&app_state, app_state.policy.unacknowledge(&name), &name)
+}
+
+fn acknowledged(app_state: &AppState, alert: Option<Alert>, name: &str) -> HttpResponse {
+    match alert {
+        Some(alert) => {
+            println!(
+                "[{}] Alert {} by {}",
+                name,
+                if alert.acknowledged() { "acknowledged" } else { "unacknowledged" },
+                alert.acknowledged_by.as_deref().unwrap_or("-")
+            );
+            persist_alert(app_state, AlertChange::Updated(alert.clone()));
+            HttpResponse::Ok().json(alert)
+        },
+        None => HttpResponse::NotFound().body(format!("no open alert for target: {}", name)),
+    }
+}
+
+#[derive(Deserialize)]
+struct NotifyTestQuery {
+    /// Only this notifier, all notifiers if unset
+    notifier: Option<String>,
+    /// Target whose current status is sent, the first target if unset
+    target: Option<String>,
+}
+
+#[derive(Serialize)]
+struct NotifyTestResult<'a> {
+    notifier: &'a str,
+    sent: bool,
+    error: Option<String>,
+}
+
+/// Sends a test event to the notifiers, once and without filters, and reports what each sink answered
+#[post("/notify/test")]
+async fn notify_test_endpoint(app_state: web::Data<AppState>, query: web::Query<NotifyTestQuery>) -> impl Responder {
+    let target = match &query.target {
+        Some(name) => match app_state.target(name) {
+            Some(target) => target,
+            None => return HttpResponse::NotFound().body(format!("unknown target: {}", name)),
+        },
+        None => &app_state.targets[0],
+    };
+    let notifiers: Vec<&NotifierConfig> = app_state
+        .notifiers
+        .iter()
+        .filter(|n| query.notifier.as_ref().is_none_or(|name| &n.name == name))
+        .collect();
+    if notifiers.is_empty() {
+        return HttpResponse::NotFound().body("no such notifier");
+    }
+
+    let status = target.current_status();
+    let event = Event {
+        kind: EventKind::Test,
+        target: target.config.name.clone(),
+        at: chrono::Utc::now(),
+        from: status.state,
+        to: status.state,
+        reasons: status.reasons,
+        previous_lag_level: status.lag_level,
+        lag_level: status.lag_level,
+        blocks_behind: status.blocks_behind,
+        lag_seconds: status.lag_seconds,
+        synced_block: status.synced_block_height,
+        chain_head: status.chain_head_block_height,
+        deployment: status.deployment,
+    };
+    let results = futures::future::join_all(notifiers.into_iter().map(|notifier| async {
+        let result = notifier.send(&app_state.client, &event).await;
+        NotifyTestResult {
+            notifier: &notifier.name,
+            sent: result.is_ok(),
+            error: result.err().map(|e| e.to_string()),
+        }
+    }))
+    .await;
+
+    HttpResponse::Ok().json(results)
 }
 
 #[get("/metrics")]
 async fn metrics_endpoint(app_state: web::Data<AppState>) -> impl Responder {
+    // the checkers can't report that they are stuck, so these are computed on scrape
+    let metrics = &app_state.metrics;
+    for target in &app_state.targets {
+        let name = target.config.name.as_str();
+        let since = target.last_completed.lock().unwrap().elapsed();
+        metrics.seconds_since_check.with_label_values(&[name]).set(since.as_secs_f64());
+        let stale = if target.stale_for().is_some() { 1 } else { 0 };
+        metrics.reason.with_label_values(&[name, Reason::MonitorStale.as_str()]).set(stale);
+    }
+
     let encoder = prometheus::TextEncoder::new();
     let metric_families = app_state.registry.gather();
 
@@ -380,68 +1687,435 @@ async fn metrics_endpoint(app_state: web::Data<AppState>) -> impl Responder {
     }
 }
 
+/// Checks a target on its interval, recording how long checks take and the ticks they miss
+async fn run_checks(app_state: web::Data<AppState>, target: Arc<Target>) {
+    let period = Duration::from_secs(target.config.interval);
+    let labels = [target.config.name.as_str()];
+    let metrics = &app_state.metrics;
+
+    // the first tick completes immediately
+    let mut interval = time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        interval.tick().await;
+        let started = Instant::now();
+        check_subgraph(app_state.clone(), target.clone()).await;
+        let elapsed = started.elapsed();
+        *target.last_completed.lock().unwrap() = Instant::now();
+
+        metrics.check_duration.with_label_values(&labels).observe(elapsed.as_secs_f64());
+        // ticks that passed during a slow check are skipped, not run back to back
+        let missed = (elapsed.as_secs_f64() / period.as_secs_f64()) as u64;
+        if missed > 0 {
+            eprintln!(
+                "[{}] Check took {:.1}s, longer than the interval, {} ticks missed",
+                target.config.name, elapsed.as_secs_f64(), missed
+            );
+            metrics.missed_ticks.with_label_values(&labels).inc_by(missed);
+        }
+    }
+}
+
+/// History of a target as persisted before the last restart
+fn restore_history(store: Option<&Store>, target: &str, capacity: usize) -> History {
+    let mut history = History::new(capacity);
+    if let Some(store) = store {
+        match store.recent_checks(target, capacity) {
+            Ok(records) => {
+                println!("[{}] Restored {} checks from store", target, records.len());
+                records.into_iter().for_each(|record| history.push(record));
+            },
+            Err(e) => eprintln!("[{}] Error restoring checks from store: {}", target, e),
+        }
+    }
+    history
+}
+
+/// Registers a new metric family, as returned by the `new` of its type
+fn register<M: Collector + Clone + 'static>(registry: &Registry, family: prometheus::Result<M>) -> M {
+    let family = family.unwrap();
+    registry.register(Box::new(family.clone())).unwrap();
+    family
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let args = Args::parse();
 
+    let Config { targets, status_codes, http, history_size, storage, notifiers, notify_policy, alertmanager } = match &args.config {
+        Some(path) => Config::load(path)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?,
+        None => Config::from_args(args.endpoint.clone(), args.rpc.clone(), args.interval),
+    };
+    let client = http.build_client().map_err(std::io::Error::other)?;
+    let deadline = time::Instant::now() + VERIFY_DEADLINE;
+    let verified = futures::future::join_all(targets.iter().map(|target| verify_chain_ids(&client, target, deadline))).await;
+    for result in verified {
+        result.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    }
+    let store = match &storage {
+        Some(storage) => Some(Arc::new(Store::open(&storage.path).map_err(std::io::Error::other)?)),
+        None => None,
+    };
+
+    // alerts of targets that have been removed from the config are dropped
+    let alerts = match &store {
+        Some(store) => store.alerts().map_err(std::io::Error::other)?,
+        None => Vec::new(),
+    };
+    let alerts = alerts.into_iter().filter(|a| targets.iter().any(|t| t.name == a.target)).collect();
+
     println!("Subgraph Block Height Monitor");
     println!("-----------------------------");
-    println!("Monitoring subgraph at: {}", args.endpoint);
-    println!("Using RPC endpoint: {}", args.rpc);
-    println!("Check interval: {} seconds", args.interval);
+    for target in &targets {
+        println!("[{}] Monitoring subgraph at: {}", target.name, target.subgraph_url);
+        for rpc_url in &target.rpc_urls {
+            println!("[{}] Using RPC endpoint: {}", target.name, rpc_url);
+        }
+        if let Some(index_node_url) = &target.index_node_url {
+            println!("[{}] Using index node: {}", target.name, index_node_url);
+        }
+        println!("[{}] Check interval: {} seconds", target.name, target.interval);
+    }
+    if let Some(alertmanager) = &alertmanager {
+        println!("Pushing alerts to Alertmanager at: {}", alertmanager.url);
+    }
+    for notifier in &notifiers {
+        println!("Notifying {} ({}) of {} and worse", notifier.name, notifier.kind.as_str(), notifier.min_severity.as_str());
+    }
     println!("Server running at: http://localhost:{}", args.port);
 
     // create metrics
     let registry = Registry::new();
 
-    let healthy_gauge = IntGauge::new("subgraph_healthy", "Whether the subgraph is healthy").unwrap();
-    let synced_block_gauge = IntGauge::new("subgraph_synced_block", "The latest indexed block height").unwrap();
-    let chain_head_gauge = IntGauge::new("subgraph_chain_head", "The current chain head block height").unwrap();
-    let blocks_behind_gauge = IntGauge::new("subgraph_blocks_behind", "How many blocks behind the subgraph is").unwrap();
+    let info_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_info", "Always 1, labels the target with its network, deployment and endpoint host"),
+        &["target", "network", "deployment", "endpoint"],
+    ));
+
+    let healthy_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_healthy", "Whether the subgraph is healthy"),
+        &["target"],
+    ));
+    let synced_block_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_synced_block", "The latest indexed block height"),
+        &["target"],
+    ));
+    let chain_head_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_chain_head", "The current chain head block height"),
+        &["target"],
+    ));
+    let blocks_behind_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_blocks_behind", "How many blocks behind the subgraph is"),
+        &["target"],
+    ));
+
+    let lag_seconds_gauge = register(&registry, GaugeVec::new(
+        Opts::new("subgraph_lag_seconds", "Age of the synced block in seconds"),
+        &["target"],
+    ));
+
+    let indexing_rate_gauge = register(&registry, GaugeVec::new(
+        Opts::new("subgraph_indexing_rate_blocks_per_second", "Blocks indexed per second over the rate window"),
+        &["target"],
+    ));
+    let chain_rate_gauge = register(&registry, GaugeVec::new(
+        Opts::new("subgraph_chain_rate_blocks_per_second", "Blocks produced by the chain per second over the rate window"),
+        &["target"],
+    ));
+    let sync_eta_gauge = register(&registry, GaugeVec::new(
+        Opts::new("subgraph_sync_eta_seconds", "Estimated seconds until the subgraph is synced, +Inf if it is falling behind"),
+        &["target"],
+    ));
+
+    let state_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_health_state", "1 for the current health state of the target, 0 for the others"),
+        &["target", "state"],
+    ));
+    let reason_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_health_reason", "1 if the reason currently applies to the target"),
+        &["target", "reason"],
+    ));
+
+    let seconds_since_progress_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_seconds_since_progress", "Seconds since the synced block last advanced"),
+        &["target"],
+    ));
+    let seconds_since_head_advanced_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_chain_head_seconds_since_advance", "Seconds since the chain head last advanced"),
+        &["target"],
+    ));
+
+    let consecutive_failures_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_consecutive_failures", "Number of consecutive checks that were not healthy"),
+        &["target"],
+    ));
+    let consecutive_successes_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_consecutive_successes", "Number of consecutive checks that were healthy"),
+        &["target"],
+    ));
+
+    let hash_mismatch_counter = register(&registry, IntCounterVec::new(
+        Opts::new("subgraph_block_hash_mismatches_total", "Checks where the synced block hash differed from the RPC"),
+        &["target"],
+    ));
+
+    let request_attempts_counter = register(&registry, IntCounterVec::new(
+        Opts::new("subgraph_request_attempts_total", "HTTP requests sent, including retries"),
+        &["target", "query", "endpoint"],
+    ));
+    let request_retries_counter = register(&registry, IntCounterVec::new(
+        Opts::new("subgraph_request_retries_total", "HTTP requests retried after a failure"),
+        &["target", "query", "endpoint"],
+    ));
+    let request_timeouts_counter = register(&registry, IntCounterVec::new(
+        Opts::new("subgraph_request_timeouts_total", "HTTP requests that timed out"),
+        &["target", "query", "endpoint"],
+    ));
+    let request_successes_counter = register(&registry, IntCounterVec::new(
+        Opts::new("subgraph_request_successes_total", "HTTP requests that succeeded"),
+        &["target", "query", "endpoint"],
+    ));
+    let request_failures_counter = register(&registry, IntCounterVec::new(
+        Opts::new("subgraph_request_failures_total", "HTTP requests that failed, by class: timeout, connect, http_status, decode, graphql, other"),
+        &["target", "query", "endpoint", "class"],
+    ));
+    let request_duration_histogram = register(&registry, HistogramVec::new(
+        HistogramOpts::new("subgraph_request_duration_seconds", "Duration of HTTP requests, every retry observed on its own"),
+        &["target", "query", "endpoint"],
+    ));
+    let checks_counter = register(&registry, IntCounterVec::new(
+        Opts::new("subgraph_checks_total", "Checks run, by the state they observed"),
+        &["target", "state"],
+    ));
+    let check_duration_histogram = register(&registry, HistogramVec::new(
+        HistogramOpts::new("subgraph_check_duration_seconds", "Duration of a whole check, including retries"),
+        &["target"],
+    ));
+    let missed_ticks_counter = register(&registry, IntCounterVec::new(
+        Opts::new("subgraph_missed_ticks_total", "Scheduled checks skipped because the previous check outlasted the interval"),
+        &["target"],
+    ));
+    let checker_restarts_counter = register(&registry, IntCounterVec::new(
+        Opts::new("subgraph_checker_restarts_total", "Times the checker of the target crashed and was restarted"),
+        &["target"],
+    ));
+    let seconds_since_check_gauge = register(&registry, GaugeVec::new(
+        Opts::new("subgraph_seconds_since_check", "Seconds since the last check of the target completed"),
+        &["target"],
+    ));
+
+    let rpc_head_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_rpc_head", "Chain head reported by each RPC provider, 0 if it failed"),
+        &["target", "provider"],
+    ));
+    let rpc_latency_gauge = register(&registry, GaugeVec::new(
+        Opts::new("subgraph_rpc_latency_seconds", "Time taken by each RPC provider to report its head"),
+        &["target", "provider"],
+    ));
+    let rpc_disagrees_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_rpc_disagrees", "1 if the RPC provider's head is further from the selected head than the tolerance"),
+        &["target", "provider"],
+    ));
+    let rpc_chain_id_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_rpc_chain_id", "Chain ID reported by each RPC provider"),
+        &["target", "provider"],
+    ));
 
-    registry.register(Box::new(healthy_gauge.clone())).unwrap();
-    registry.register(Box::new(synced_block_gauge.clone())).unwrap();
-    registry.register(Box::new(chain_head_gauge.clone())).unwrap();
-    registry.register(Box::new(blocks_behind_gauge.clone())).unwrap();
+    let index_node_health_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_index_node_health", "1 for the health graph-node reports for the subgraph, 0 for the others"),
+        &["target", "health"],
+    ));
+    let index_node_synced_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_index_node_synced", "1 if graph-node reports the subgraph as synced"),
+        &["target"],
+    ));
+    let fatal_error_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_fatal_error", "1 if graph-node reports a fatal indexing error"),
+        &["target"],
+    ));
+    let non_fatal_errors_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_non_fatal_errors", "Number of non-fatal indexing errors reported by graph-node"),
+        &["target"],
+    ));
+    let index_node_chain_head_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_index_node_chain_head", "Chain head block as seen by graph-node"),
+        &["target"],
+    ));
+    let earliest_block_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_earliest_block", "First block indexed by the subgraph"),
+        &["target"],
+    ));
+
+    let deployment_changes_counter = register(&registry, IntCounterVec::new(
+        Opts::new("subgraph_deployment_changes_total", "Times the deployment served by the subgraph endpoint changed"),
+        &["target"],
+    ));
+    let pending_synced_block_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_pending_synced_block", "Latest block indexed by the pending version, 0 if there is none"),
+        &["target"],
+    ));
+    let ingestor_lag_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_ingestor_lag_blocks", "Blocks graph-node's chain head trails the RPC chain head"),
+        &["target"],
+    ));
+    let blocks_behind_graph_node_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_blocks_behind_graph_node", "Blocks the subgraph trails graph-node's chain head"),
+        &["target"],
+    ));
+    let notifications_counter = register(&registry, IntCounterVec::new(
+        Opts::new("subgraph_notifications_total", "Notifications of state changes by notifier and result"),
+        &["target", "notifier", "result"],
+    ));
+
+    let lag_threshold_blocks_gauge = register(&registry, IntGaugeVec::new(
+        Opts::new("subgraph_lag_threshold_blocks", "Configured lag threshold in blocks"),
+        &["target", "level"],
+    ));
+    let lag_threshold_seconds_gauge = register(&registry, GaugeVec::new(
+        Opts::new("subgraph_lag_threshold_seconds", "Configured lag threshold in seconds"),
+        &["target", "level"],
+    ));
+
+    // thresholds are static, export them once
+    for target in &targets {
+        let thresholds = &target.thresholds;
+        let name = target.name.as_str();
+        if let Some(warn) = thresholds.warn_blocks {
+            lag_threshold_blocks_gauge.with_label_values(&[name, "warn"]).set(warn);
+        }
+        lag_threshold_blocks_gauge.with_label_values(&[name, "critical"]).set(thresholds.critical_blocks);
+        if let Some(warn) = thresholds.warn_seconds {
+            lag_threshold_seconds_gauge.with_label_values(&[name, "warn"]).set(warn);
+        }
+        if let Some(critical) = thresholds.critical_seconds {
+            lag_threshold_seconds_gauge.with_label_values(&[name, "critical"]).set(critical);
+        }
+    }
 
     let metrics = Arc::new(SubgraphMetrics {
+        info: info_gauge,
         healthy: healthy_gauge,
         synced_block: synced_block_gauge,
         chain_head: chain_head_gauge,
         blocks_behind: blocks_behind_gauge,
+        lag_seconds: lag_seconds_gauge,
+        indexing_rate: indexing_rate_gauge,
+        chain_rate: chain_rate_gauge,
+        sync_eta: sync_eta_gauge,
+        state: state_gauge,
+        reason: reason_gauge,
+        consecutive_failures: consecutive_failures_gauge,
+        consecutive_successes: consecutive_successes_gauge,
+        hash_mismatches: hash_mismatch_counter,
+        seconds_since_progress: seconds_since_progress_gauge,
+        seconds_since_head_advanced: seconds_since_head_advanced_gauge,
+        request_attempts: request_attempts_counter,
+        request_retries: request_retries_counter,
+        request_timeouts: request_timeouts_counter,
+        request_successes: request_successes_counter,
+        request_failures: request_failures_counter,
+        request_duration: request_duration_histogram,
+        checks: checks_counter,
+        check_duration: check_duration_histogram,
+        missed_ticks: missed_ticks_counter,
+        checker_restarts: checker_restarts_counter,
+        seconds_since_check: seconds_since_check_gauge,
+        rpc_head: rpc_head_gauge,
+        rpc_latency: rpc_latency_gauge,
+        rpc_disagrees: rpc_disagrees_gauge,
+        rpc_chain_id: rpc_chain_id_gauge,
+        index_node_health: index_node_health_gauge,
+        index_node_synced: index_node_synced_gauge,
+        fatal_error: fatal_error_gauge,
+        non_fatal_errors: non_fatal_errors_gauge,
+        index_node_chain_head: index_node_chain_head_gauge,
+        earliest_block: earliest_block_gauge,
+        deployment_changes: deployment_changes_counter,
+        pending_synced_block: pending_synced_block_gauge,
+        ingestor_lag: ingestor_lag_gauge,
+        blocks_behind_graph_node: blocks_behind_graph_node_gauge,
+        notifications: notifications_counter,
     });
 
     // initialize app state
     let app_state = web::Data::new(AppState {
-        subgraph_url: args.endpoint.clone(),
-        rpc_url: args.rpc.clone(),
-        status: Arc::new(Mutex::new(SubgraphStatus {
-            healthy: false,
-            synced_block_height: 0,
-            chain_head_block_height: 0,
-            blocks_behind: 0,
-            last_checked: "".to_string(),
-        })),
+        targets: targets
+            .into_iter()
+            .map(|config| {
+                let history = restore_history(store.as_deref(), &config.name, history_size);
+                Arc::new(Target {
+                    providers: chain_head::provider_labels(&config.rpc_urls),
+                    endpoint: chain_head::provider_label(&config.subgraph_url),
+                    status: Mutex::new(SubgraphStatus::new(match config.startup {
+                        Startup::Restore => history.last(),
+                        _ => None,
+                    })),
+                    history: Mutex::new(history),
+                    chain_ids: Mutex::new(vec![None; config.rpc_urls.len()]),
+                    last_completed: Mutex::new(Instant::now()),
+                    meta_timestamp: AtomicBool::new(config.block_timestamp == TimestampSource::Meta),
+                    config,
+                })
+            })
+            .collect(),
+        status_codes,
+        client,
+        http,
         registry,
         metrics,
+        store: store.clone(),
+        notifiers,
+        policy: Policy::new(notify_policy, alerts),
+        alertmanager: alertmanager.map(Alertmanager::new),
     });
 
-    // clone for the background task
-    let app_state_clone = app_state.clone();
+    // apply retention and compaction to the store periodically
+    if let (Some(store), Some(storage)) = (store, storage) {
+        tokio::spawn(async move {
+            let mut interval = time::interval(Duration::from_secs(storage.maintenance_interval));
+            loop {
+                interval.tick().await;
+                let store = store.clone();
+                let storage = storage.clone();
+                match tokio::task::spawn_blocking(move || store.maintain(&storage)).await {
+                    Ok(Ok(0)) => {},
+                    Ok(Ok(deleted)) => println!("Store maintenance: deleted {} checks", deleted),
+                    Ok(Err(e)) => eprintln!("Error maintaining store: {}", e),
+                    Err(e) => eprintln!("Error maintaining store: {}", e),
+                }
+            }
+        });
+    }
 
-    // start background task for checking subgraph
-    tokio::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(args.interval));
+    if !app_state.notifiers.is_empty() {
+        tokio::spawn(run_notify_policy(app_state.clone()));
+    }
 
-        // run initial check
-        check_subgraph(app_state_clone.clone()).await;
+    // start one checker per target, restarted if it panics
+    for target in &app_state.targets {
+        let app_state = app_state.clone();
+        let target = target.clone();
 
-        // schedule regular checks
-        loop {
-            interval.tick().await;
-            check_subgraph(app_state_clone.clone()).await;
-        }
-    });
+        tokio::spawn(async move {
+            loop {
+                let checker = tokio::spawn(run_checks(app_state.clone(), target.clone()));
+                match checker.await {
+                    Err(e) if e.is_panic() => {
+                        eprintln!("[{}] Checker crashed, restarting: {}", target.config.name, e);
+                        app_state.metrics.checker_restarts.with_label_values(&[&target.config.name]).inc();
+                        // a panic while holding a lock must not take the next checker down with it
+                        target.status.clear_poison();
+                        target.history.clear_poison();
+                        target.chain_ids.clear_poison();
+                        target.last_completed.clear_poison();
+                        time::sleep(Duration::from_secs(1)).await;
+                    },
+                    _ => break,
+                }
+            }
+        });
+    }
 
     // start HTTP server
     HttpServer::new(move || {
@@ -449,6 +2123,14 @@ async fn main() -> std::io::Result<()> {
             .app_data(app_state.clone())
             .service(web::resource("/").to(dashboard::render_dashboard))
             .service(health_endpoint)
+            .service(target_health_endpoint)
+            .service(history_endpoint)
+            .service(incidents_endpoint)
+            .service(deployments_endpoint)
+            .service(alerts_endpoint)
+            .service(ack_endpoint)
+            .service(unack_endpoint)
+            .service(notify_test_endpoint)
             .service(metrics_endpoint)
     })
     .bind(("0.0.0.0", args.port))?
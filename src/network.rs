@@ -0,0 +1,38 @@
+/// Chain IDs of networks by the name graph-node reports them under
+const NETWORKS: &[(&str, u64)] = &[
+    ("mainnet", 1),
+    ("sepolia", 11155111),
+    ("holesky", 17000),
+    ("optimism", 10),
+    ("bsc", 56),
+    ("gnosis", 100),
+    ("xdai", 100),
+    ("matic", 137),
+    ("fantom", 250),
+    ("base", 8453),
+    ("arbitrum-one", 42161),
+    ("celo", 42220),
+    ("avalanche", 43114),
+    ("linea", 59144),
+    ("scroll", 534352),
+    ("flare", 14),
+    ("songbird", 19),
+    ("coston", 16),
+    ("coston2", 114),
+];
+
+/// Chain ID of a graph-node network name, `None` for networks not listed here
+pub fn chain_id(network: &str) -> Option<u64> {
+    NETWORKS
+        .iter()
+        .find(|(name, _)| *name == network)
+        .map(|&(_, id)| id)
+}
+
+/// Network name of a chain ID, the first listed if several names share it
+pub fn name(chain_id: u64) -> Option<&'static str> {
+    NETWORKS
+        .iter()
+        .find(|(_, id)| *id == chain_id)
+        .map(|&(name, _)| name)
+}
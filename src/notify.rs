@@ -0,0 +1,309 @@
+use crate::client::BoxError;
+use crate::config::LagLevel;
+use crate::health::{HealthState, Reason};
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+const PAGERDUTY_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+const TELEGRAM_URL: &str = "https://api.telegram.org";
+
+const DEFAULT_TEMPLATE: &str = "{summary} (synced block {synced_block}, chain head {chain_head}, {blocks_behind} blocks behind)";
+
+/// Placeholders a message template may use, as `{name}`
+const PLACEHOLDERS: [&str; 14] = [
+    "summary",
+    "event",
+    "target",
+    "from",
+    "to",
+    "reasons",
+    "lag_level",
+    "blocks_behind",
+    "lag_seconds",
+    "synced_block",
+    "chain_head",
+    "deployment",
+    "at",
+    "severity",
+];
+
+/// Service a notifier posts to
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkKind {
+    /// The event as JSON, with the rendered `message`
+    Webhook,
+    /// Slack incoming webhook
+    Slack,
+    /// Discord webhook
+    Discord,
+    /// Telegram bot `sendMessage`
+    Telegram,
+    /// PagerDuty Events API v2, recoveries resolve the incident
+    Pagerduty,
+}
+
+/// A sink notified when a target changes state
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NotifierConfig {
+    /// Unique name, used in `/notify/test` and as the `notifier` metric label
+    pub name: String,
+    pub kind: SinkKind,
+    /// Webhook URL, or the API URL of `telegram` and `pagerduty` if not the public one
+    pub url: Option<String>,
+    /// Integration key of `pagerduty`
+    pub routing_key: Option<String>,
+    /// Bot token of `telegram`
+    pub bot_token: Option<String>,
+    /// Chat the `telegram` bot posts to
+    pub chat_id: Option<String>,
+    /// Least severe events sent, `degraded` (default) or `down`; an event is as severe as the
+    /// worse side of the change
+    #[serde(default = "default_min_severity")]
+    pub min_severity: HealthState,
+    /// Only events of these targets, all targets if empty
+    #[serde(default)]
+    pub targets: Vec<String>,
+    /// Message with `{placeholder}`s, see `PLACEHOLDERS`
+    pub template: Option<String>,
+}
+
+fn default_min_severity() -> HealthState {
+    HealthState::Degraded
+}
+
+impl NotifierConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let missing = |field: &str| format!("notifier {:?}: {} requires {}", self.name, self.kind.as_str(), field);
+        match self.kind {
+            SinkKind::Webhook | SinkKind::Slack | SinkKind::Discord if self.url.is_none() => {
+                return Err(missing("url"));
+            },
+            SinkKind::Telegram if self.bot_token.is_none() || self.chat_id.is_none() => {
+                return Err(missing("bot_token and chat_id"));
+            },
+            SinkKind::Pagerduty if self.routing_key.is_none() => {
+                return Err(missing("routing_key"));
+            },
+            _ => {},
+        }
+        if let Some(template) = &self.template {
+            if let Some(unknown) = placeholders(template).find(|p| !PLACEHOLDERS.contains(p)) {
+                return Err(format!("notifier {:?}: unknown placeholder {{{}}} in template", self.name, unknown));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the notifier wants an event
+    pub fn accepts(&self, event: &Event) -> bool {
+        self.wants(&event.target, event.severity())
+    }
+
+    /// Whether the notifier wants events of a target at a severity
+    pub fn wants(&self, target: &str, severity: HealthState) -> bool {
+        (self.targets.is_empty() || self.targets.iter().any(|t| t == target)) && severity >= self.min_severity
+    }
+
+    /// The event as a message for a chat
+    pub fn render(&self, event: &Event) -> String {
+        let template = self.template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+        let mut message = template.to_string();
+        for placeholder in placeholders(template) {
+            message = message.replace(&format!("{{{}}}", placeholder), &event.field(placeholder));
+        }
+        message
+    }
+
+    pub async fn send(&self, client: &Client, event: &Event) -> Result<(), BoxError> {
+        let message = self.render(event);
+        let (url, body) = match self.kind {
+            SinkKind::Webhook => {
+                let mut body = serde_json::to_value(event)?;
+                body["message"] = message.into();
+                (self.url.clone().unwrap_or_default(), body)
+            },
+            SinkKind::Slack => (self.url.clone().unwrap_or_default(), serde_json::json!({ "text": message })),
+            SinkKind::Discord => (self.url.clone().unwrap_or_default(), serde_json::json!({ "content": message })),
+            SinkKind::Telegram => (
+                format!(
+                    "{}/bot{}/sendMessage",
+                    self.url.as_deref().unwrap_or(TELEGRAM_URL).trim_end_matches('/'),
+                    self.bot_token.as_deref().unwrap_or_default()
+                ),
+                serde_json::json!({ "chat_id": self.chat_id, "text": message }),
+            ),
+            SinkKind::Pagerduty => (
+                self.url.clone().unwrap_or_else(|| PAGERDUTY_URL.to_string()),
+                pagerduty_event(self.routing_key.as_deref().unwrap_or_default(), event, &message),
+            ),
+        };
+
+        // the URL holds the bot token or the webhook secret, keep it out of errors
+        let response = client.post(url).json(&body).send().await.map_err(reqwest::Error::without_url)?;
+        response.error_for_status().map_err(reqwest::Error::without_url)?;
+        Ok(())
+    }
+}
+
+impl SinkKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SinkKind::Webhook => "webhook",
+            SinkKind::Slack => "slack",
+            SinkKind::Discord => "discord",
+            SinkKind::Telegram => "telegram",
+            SinkKind::Pagerduty => "pagerduty",
+        }
+    }
+}
+
+/// `{name}` placeholders of a template
+fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+}
+
+/// Trigger, or on recovery resolve, the PagerDuty incident of the target
+fn pagerduty_event(routing_key: &str, event: &Event, message: &str) -> serde_json::Value {
+    let action = if event.kind == EventKind::Recovered { "resolve" } else { "trigger" };
+    let severity = match event.severity() {
+        HealthState::Down => "critical",
+        HealthState::Degraded => "warning",
+        _ => "info",
+    };
+    serde_json::json!({
+        "routing_key": routing_key,
+        "event_action": action,
+        "dedup_key": format!("subgraph-monitor/{}", event.target),
+        "payload": {
+            "summary": message,
+            "source": event.target,
+            "severity": severity,
+            "timestamp": event.at,
+            "custom_details": event,
+        },
+    })
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// The target went down
+    Down,
+    /// The target went degraded, from healthy or down
+    Degraded,
+    /// The target is healthy again
+    Recovered,
+    /// The lag crossed a threshold without changing the reported state
+    Lag,
+    /// Sent by `/notify/test`
+    Test,
+    /// The target is still unhealthy, sent every `repeat_interval`
+    Reminder,
+    /// The target has been unhealthy for `escalate_after`, sent to `escalate_to`
+    Escalation,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Down => "down",
+            EventKind::Degraded => "degraded",
+            EventKind::Recovered => "recovered",
+            EventKind::Lag => "lag",
+            EventKind::Test => "test",
+            EventKind::Reminder => "reminder",
+            EventKind::Escalation => "escalation",
+        }
+    }
+}
+
+/// Something worth notifying about a target
+#[derive(Serialize, Clone, Debug)]
+pub struct Event {
+    pub kind: EventKind,
+    pub target: String,
+    pub at: DateTime<Utc>,
+    pub from: HealthState,
+    pub to: HealthState,
+    pub reasons: Vec<Reason>,
+    pub previous_lag_level: LagLevel,
+    pub lag_level: LagLevel,
+    pub blocks_behind: i64,
+    pub lag_seconds: Option<f64>,
+    pub synced_block: i64,
+    pub chain_head: i64,
+    pub deployment: Option<String>,
+}
+
+fn lag_severity(level: LagLevel) -> HealthState {
+    match level {
+        LagLevel::Ok => HealthState::Healthy,
+        LagLevel::Warn => HealthState::Degraded,
+        LagLevel::Critical => HealthState::Down,
+    }
+}
+
+impl Event {
+    /// Kind of a change of the reported state
+    pub fn transition_kind(to: HealthState) -> EventKind {
+        match to {
+            HealthState::Healthy => EventKind::Recovered,
+            HealthState::Degraded => EventKind::Degraded,
+            HealthState::Down | HealthState::Unknown => EventKind::Down,
+        }
+    }
+
+    /// Worst state on either side of the change, so recoveries reach the sinks that were
+    /// notified of the failure
+    pub fn severity(&self) -> HealthState {
+        match self.kind {
+            EventKind::Lag => lag_severity(self.previous_lag_level).max(lag_severity(self.lag_level)),
+            EventKind::Test => HealthState::Down,
+            _ => self.from.max(self.to),
+        }
+    }
+
+    fn summary(&self) -> String {
+        let reasons: Vec<&str> = self.reasons.iter().map(Reason::as_str).collect();
+        match self.kind {
+            EventKind::Down => format!("{} is down: {}", self.target, reasons.join(", ")),
+            EventKind::Degraded => format!("{} is degraded: {}", self.target, reasons.join(", ")),
+            EventKind::Recovered => format!("{} recovered, was {}", self.target, self.from.as_str()),
+            EventKind::Lag => format!("{} lag is {}", self.target, self.lag_level.as_str()),
+            EventKind::Test => format!("Test notification for {}", self.target),
+            EventKind::Reminder => format!("{} is still {}: {}", self.target, self.to.as_str(), reasons.join(", ")),
+            EventKind::Escalation => format!(
+                "{} is {} and unacknowledged, escalated: {}",
+                self.target,
+                self.to.as_str(),
+                reasons.join(", ")
+            ),
+        }
+    }
+
+    fn field(&self, placeholder: &str) -> String {
+        match placeholder {
+            "summary" => self.summary(),
+            "event" => self.kind.as_str().to_string(),
+            "target" => self.target.clone(),
+            "from" => self.from.as_str().to_string(),
+            "to" => self.to.as_str().to_string(),
+            "reasons" => self.reasons.iter().map(Reason::as_str).collect::<Vec<_>>().join(", "),
+            "lag_level" => self.lag_level.as_str().to_string(),
+            "blocks_behind" => self.blocks_behind.to_string(),
+            "lag_seconds" => self.lag_seconds.map_or("-".to_string(), |s| format!("{:.0}", s)),
+            "synced_block" => self.synced_block.to_string(),
+            "chain_head" => self.chain_head.to_string(),
+            "deployment" => self.deployment.clone().unwrap_or_else(|| "-".to_string()),
+            "at" => self.at.to_rfc3339(),
+            "severity" => self.severity().as_str().to_string(),
+            _ => format!("{{{}}}", placeholder),
+        }
+    }
+}
@@ -0,0 +1,488 @@
+use crate::health::{HealthState, Reason};
+use crate::notify::{Event, EventKind, NotifierConfig};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// When and how often notifiers are notified of a target
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// Minimum seconds between two notifications of a target to the same notifier, changes in
+    /// between are held back and only the latest is sent once the interval has passed
+    #[serde(default = "default_renotify_interval")]
+    pub renotify_interval: u64,
+    /// Seconds between reminders while a target is still unhealthy and unacknowledged, none if unset
+    pub repeat_interval: Option<u64>,
+    /// Seconds a target may stay unhealthy and unacknowledged before `escalate_to` is notified
+    pub escalate_after: Option<u64>,
+    /// Notifier that only receives escalations, and every later event of an escalated alert
+    pub escalate_to: Option<String>,
+}
+
+fn default_renotify_interval() -> u64 {
+    300
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        PolicyConfig {
+            renotify_interval: default_renotify_interval(),
+            repeat_interval: None,
+            escalate_after: None,
+            escalate_to: None,
+        }
+    }
+}
+
+impl PolicyConfig {
+    pub fn validate(&self, notifiers: &[NotifierConfig]) -> Result<(), String> {
+        if self.repeat_interval == Some(0) {
+            return Err("notify_policy repeat_interval must be greater than 0".to_string());
+        }
+        if self.escalate_after.is_some() != self.escalate_to.is_some() {
+            return Err("notify_policy escalate_after and escalate_to must be set together".to_string());
+        }
+        if let Some(name) = &self.escalate_to {
+            if !notifiers.iter().any(|n| &n.name == name) {
+                return Err(format!("notify_policy escalate_to: unknown notifier {:?}", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A target that is not healthy, from the first failure until it recovers
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Alert {
+    pub target: String,
+    pub opened_at: DateTime<Utc>,
+    pub state: HealthState,
+    pub reasons: Vec<Reason>,
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    /// Whether `escalate_to` has been notified
+    pub escalated: bool,
+}
+
+impl Alert {
+    pub fn acknowledged(&self) -> bool {
+        self.acknowledged_at.is_some()
+    }
+}
+
+/// How the alert of a target changed with an event, to be persisted
+pub enum AlertChange {
+    Updated(Alert),
+    Closed(String),
+}
+
+/// What happens to an event for a notifier
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Send,
+    /// The notifier already knows, the last notification had the same dedup key
+    Deduplicated,
+    /// Within `renotify_interval` of the last notification, sent later unless superseded
+    Delayed,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Send => "sent",
+            Outcome::Deduplicated => "deduplicated",
+            Outcome::Delayed => "delayed",
+        }
+    }
+}
+
+/// A notification the policy wants sent without a new event
+pub enum Due {
+    /// An event held back by `renotify_interval`
+    Delayed(usize, Event),
+    /// The target is still unhealthy
+    Reminder(usize, Alert),
+    /// The target has been unhealthy for `escalate_after`
+    Escalation(usize, Alert),
+}
+
+/// Last notification of a target to a notifier
+struct Delivery {
+    key: String,
+    sent_at: DateTime<Utc>,
+    delayed: Option<Event>,
+}
+
+pub struct Policy {
+    config: PolicyConfig,
+    /// Keyed by notifier index and target
+    deliveries: Mutex<HashMap<(usize, String), Delivery>>,
+    alerts: Mutex<BTreeMap<String, Alert>>,
+}
+
+/// Events with the same key tell a notifier the same thing
+fn dedup_key(event: &Event) -> String {
+    match event.kind {
+        EventKind::Lag => format!("{}/lag/{}", event.target, event.lag_level.as_str()),
+        _ => format!("{}/{}", event.target, event.to.as_str()),
+    }
+}
+
+impl Policy {
+    pub fn new(config: PolicyConfig, alerts: Vec<Alert>) -> Policy {
+        Policy {
+            config,
+            deliveries: Mutex::new(HashMap::new()),
+            alerts: Mutex::new(alerts.into_iter().map(|a| (a.target.clone(), a)).collect()),
+        }
+    }
+
+    fn is_escalation(&self, notifier: &NotifierConfig) -> bool {
+        self.config.escalate_to.as_ref() == Some(&notifier.name)
+    }
+
+    fn renotify_interval(&self) -> Duration {
+        Duration::seconds(self.config.renotify_interval as i64)
+    }
+
+    /// Decides which notifiers an event of a state change goes to
+    pub fn route(&self, notifiers: &[NotifierConfig], event: &Event) -> Vec<(usize, Outcome)> {
+        let escalated = self.alerts.lock().unwrap().get(&event.target).is_some_and(|a| a.escalated);
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let key = dedup_key(event);
+
+        notifiers
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| if self.is_escalation(n) { escalated } else { n.accepts(event) })
+            .map(|(index, _)| {
+                let outcome = match deliveries.get_mut(&(index, event.target.clone())) {
+                    Some(delivery) if delivery.key == key => {
+                        // back to what the notifier was told last, nothing to send
+                        delivery.delayed = None;
+                        Outcome::Deduplicated
+                    },
+                    Some(delivery) if event.at - delivery.sent_at < self.renotify_interval() => {
+                        delivery.delayed = Some(event.clone());
+                        Outcome::Delayed
+                    },
+                    _ => Outcome::Send,
+                };
+                if outcome == Outcome::Send {
+                    deliveries.insert(
+                        (index, event.target.clone()),
+                        Delivery { key: key.clone(), sent_at: event.at, delayed: None },
+                    );
+                }
+                (index, outcome)
+            })
+            .collect()
+    }
+
+    /// Opens, updates or closes the alert of the event's target
+    pub fn track(&self, event: &Event) -> Option<AlertChange> {
+        if !matches!(event.kind, EventKind::Down | EventKind::Degraded | EventKind::Recovered) {
+            return None;
+        }
+        let mut alerts = self.alerts.lock().unwrap();
+        if event.to == HealthState::Healthy {
+            return alerts.remove(&event.target).map(|_| AlertChange::Closed(event.target.clone()));
+        }
+        let alert = alerts.entry(event.target.clone()).or_insert_with(|| Alert {
+            target: event.target.clone(),
+            opened_at: event.at,
+            state: event.to,
+            reasons: Vec::new(),
+            acknowledged_by: None,
+            acknowledged_at: None,
+            escalated: false,
+        });
+        alert.state = event.to;
+        alert.reasons = event.reasons.clone();
+        Some(AlertChange::Updated(alert.clone()))
+    }
+
+    /// Closes the alert of a target that is healthy on its first check since startup, an alert
+    /// loaded from the store whose target recovered while the monitor was down
+    pub fn reconcile(&self, target: &str, state: HealthState) -> Option<Alert> {
+        if state != HealthState::Healthy {
+            return None;
+        }
+        self.alerts.lock().unwrap().remove(target)
+    }
+
+    /// Notifications that are due at `now`, delayed events, reminders and escalations
+    pub fn due(&self, notifiers: &[NotifierConfig], now: DateTime<Utc>) -> Vec<Due> {
+        let mut due = Vec::new();
+        let mut deliveries = self.deliveries.lock().unwrap();
+        for (&(index, _), delivery) in deliveries.iter_mut() {
+            if now - delivery.sent_at < self.renotify_interval() {
+                continue;
+            }
+            if let Some(event) = delivery.delayed.take() {
+                delivery.key = dedup_key(&event);
+                delivery.sent_at = now;
+                due.push(Due::Delayed(index, event));
+            }
+        }
+
+        let mut alerts = self.alerts.lock().unwrap();
+        for alert in alerts.values_mut().filter(|a| !a.acknowledged()) {
+            if let Some(repeat) = self.config.repeat_interval {
+                for (index, notifier) in notifiers.iter().enumerate() {
+                    let receives = if self.is_escalation(notifier) {
+                        alert.escalated
+                    } else {
+                        notifier.wants(&alert.target, alert.state)
+                    };
+                    if !receives {
+                        continue;
+                    }
+                    let delivery = deliveries.entry((index, alert.target.clone())).or_insert_with(|| Delivery {
+                        key: format!("{}/{}", alert.target, alert.state.as_str()),
+                        sent_at: alert.opened_at,
+                        delayed: None,
+                    });
+                    if now - delivery.sent_at >= Duration::seconds(repeat as i64) {
+                        delivery.sent_at = now;
+                        due.push(Due::Reminder(index, alert.clone()));
+                    }
+                }
+            }
+
+            if let (Some(after), Some(name)) = (self.config.escalate_after, &self.config.escalate_to) {
+                if !alert.escalated && now - alert.opened_at >= Duration::seconds(after as i64) {
+                    if let Some(index) = notifiers.iter().position(|n| &n.name == name) {
+                        alert.escalated = true;
+                        deliveries.insert(
+                            (index, alert.target.clone()),
+                            Delivery {
+                                key: format!("{}/{}", alert.target, alert.state.as_str()),
+                                sent_at: now,
+                                delayed: None,
+                            },
+                        );
+                        due.push(Due::Escalation(index, alert.clone()));
+                    }
+                }
+            }
+        }
+        due
+    }
+
+    pub fn alerts(&self) -> Vec<Alert> {
+        self.alerts.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Stops reminders and escalation of the open alert of a target
+    pub fn acknowledge(&self, target: &str, by: &str, now: DateTime<Utc>) -> Option<Alert> {
+        let mut alerts = self.alerts.lock().unwrap();
+        let alert = alerts.get_mut(target)?;
+        alert.acknowledged_by = Some(by.to_string());
+        alert.acknowledged_at = Some(now);
+        Some(alert.clone())
+    }
+
+    pub fn unacknowledge(&self, target: &str) -> Option<Alert> {
+        let mut alerts = self.alerts.lock().unwrap();
+        let alert = alerts.get_mut(target)?;
+        alert.acknowledged_by = None;
+        alert.acknowledged_at = None;
+        Some(alert.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LagLevel;
+    use crate::notify::SinkKind;
+    use crate::testing::at;
+
+    fn alert(target: &str, state: HealthState) -> Alert {
+        Alert {
+            target: target.to_string(),
+            opened_at: at(0),
+            state,
+            reasons: vec![Reason::SubgraphUnreachable],
+            acknowledged_by: None,
+            acknowledged_at: None,
+            escalated: false,
+        }
+    }
+
+    fn notifier(name: &str, min_severity: HealthState) -> NotifierConfig {
+        NotifierConfig {
+            name: name.to_string(),
+            kind: SinkKind::Webhook,
+            url: Some(format!("http://127.0.0.1/{}", name)),
+            routing_key: None,
+            bot_token: None,
+            chat_id: None,
+            min_severity,
+            targets: Vec::new(),
+            template: None,
+        }
+    }
+
+    fn event(kind: EventKind, from: HealthState, to: HealthState, seconds: i64) -> Event {
+        Event {
+            kind,
+            target: "a".to_string(),
+            at: at(seconds),
+            from,
+            to,
+            reasons: if to == HealthState::Healthy { Vec::new() } else { vec![Reason::SubgraphUnreachable] },
+            previous_lag_level: LagLevel::Ok,
+            lag_level: LagLevel::Ok,
+            blocks_behind: 0,
+            lag_seconds: None,
+            synced_block: 100,
+            chain_head: 100,
+            deployment: None,
+        }
+    }
+
+    fn down(seconds: i64) -> Event {
+        event(EventKind::Down, HealthState::Healthy, HealthState::Down, seconds)
+    }
+
+    fn recovered(seconds: i64) -> Event {
+        event(EventKind::Recovered, HealthState::Down, HealthState::Healthy, seconds)
+    }
+
+    #[test]
+    fn route_deduplicates_and_delays_within_renotify_interval() {
+        let policy = Policy::new(PolicyConfig::default(), Vec::new());
+        let notifiers = [notifier("hook", HealthState::Degraded)];
+
+        assert_eq!(policy.route(&notifiers, &down(0)), vec![(0, Outcome::Send)]);
+        assert_eq!(policy.route(&notifiers, &down(10)), vec![(0, Outcome::Deduplicated)]);
+        assert_eq!(policy.route(&notifiers, &recovered(20)), vec![(0, Outcome::Delayed)]);
+        // flapping back before the interval passed, the held back recovery is dropped
+        assert_eq!(policy.route(&notifiers, &down(30)), vec![(0, Outcome::Deduplicated)]);
+        assert!(policy.due(&notifiers, at(400)).is_empty());
+
+        assert_eq!(policy.route(&notifiers, &recovered(500)), vec![(0, Outcome::Send)]);
+    }
+
+    #[test]
+    fn due_sends_the_latest_delayed_event_once_the_interval_passed() {
+        let policy = Policy::new(PolicyConfig::default(), Vec::new());
+        let notifiers = [notifier("hook", HealthState::Degraded)];
+
+        policy.route(&notifiers, &down(0));
+        policy.route(&notifiers, &recovered(20));
+        assert!(policy.due(&notifiers, at(100)).is_empty());
+
+        let due = policy.due(&notifiers, at(300));
+        assert!(matches!(due.as_slice(), [Due::Delayed(0, e)] if e.kind == EventKind::Recovered));
+        assert!(policy.due(&notifiers, at(600)).is_empty());
+        // the notifier was told about the recovery
+        assert_eq!(policy.route(&notifiers, &recovered(610)), vec![(0, Outcome::Deduplicated)]);
+    }
+
+    #[test]
+    fn route_filters_by_severity_and_escalation() {
+        let config = PolicyConfig {
+            escalate_after: Some(60),
+            escalate_to: Some("pager".to_string()),
+            ..PolicyConfig::default()
+        };
+        let policy = Policy::new(config, Vec::new());
+        let notifiers = [
+            notifier("hook", HealthState::Degraded),
+            notifier("oncall", HealthState::Down),
+            notifier("pager", HealthState::Degraded),
+        ];
+
+        let degraded = event(EventKind::Degraded, HealthState::Healthy, HealthState::Degraded, 0);
+        assert_eq!(policy.route(&notifiers, &degraded), vec![(0, Outcome::Send)]);
+        policy.track(&degraded);
+
+        // escalated alerts also go to `escalate_to`
+        assert!(matches!(policy.due(&notifiers, at(60)).as_slice(), [Due::Escalation(2, _)]));
+        let routes = policy.route(&notifiers, &event(EventKind::Down, HealthState::Degraded, HealthState::Down, 400));
+        assert_eq!(routes, vec![(0, Outcome::Send), (1, Outcome::Send), (2, Outcome::Send)]);
+    }
+
+    #[test]
+    fn track_opens_updates_and_closes_the_alert() {
+        let policy = Policy::new(PolicyConfig::default(), Vec::new());
+
+        assert!(matches!(policy.track(&down(0)), Some(AlertChange::Updated(a)) if a.opened_at == at(0)));
+        policy.acknowledge("a", "ops", at(5));
+
+        let degraded = event(EventKind::Degraded, HealthState::Down, HealthState::Degraded, 10);
+        let Some(AlertChange::Updated(alert)) = policy.track(&degraded) else {
+            panic!("alert not updated");
+        };
+        assert_eq!((alert.opened_at, alert.state), (at(0), HealthState::Degraded));
+        assert_eq!(alert.acknowledged_by.as_deref(), Some("ops"));
+
+        let lag = event(EventKind::Lag, HealthState::Degraded, HealthState::Degraded, 20);
+        assert!(policy.track(&lag).is_none());
+
+        assert!(matches!(policy.track(&recovered(30)), Some(AlertChange::Closed(t)) if t == "a"));
+        assert!(policy.alerts().is_empty());
+        assert!(policy.track(&recovered(40)).is_none());
+    }
+
+    #[test]
+    fn due_reminds_until_acknowledged() {
+        let config = PolicyConfig { repeat_interval: Some(60), ..PolicyConfig::default() };
+        let policy = Policy::new(config, Vec::new());
+        let notifiers = [notifier("hook", HealthState::Degraded), notifier("oncall", HealthState::Down)];
+
+        let degraded = event(EventKind::Degraded, HealthState::Healthy, HealthState::Degraded, 0);
+        policy.route(&notifiers, &degraded);
+        policy.track(&degraded);
+
+        assert!(policy.due(&notifiers, at(30)).is_empty());
+        // `oncall` wasn't told about the degraded target and isn't reminded of it
+        assert!(matches!(policy.due(&notifiers, at(60)).as_slice(), [Due::Reminder(0, _)]));
+        assert!(policy.due(&notifiers, at(90)).is_empty());
+        assert!(matches!(policy.due(&notifiers, at(120)).as_slice(), [Due::Reminder(0, _)]));
+
+        policy.acknowledge("a", "ops", at(130));
+        assert!(policy.due(&notifiers, at(300)).is_empty());
+        policy.unacknowledge("a");
+        assert!(matches!(policy.due(&notifiers, at(300)).as_slice(), [Due::Reminder(0, _)]));
+    }
+
+    #[test]
+    fn due_escalates_once_unless_acknowledged() {
+        let config = PolicyConfig {
+            escalate_after: Some(100),
+            escalate_to: Some("pager".to_string()),
+            ..PolicyConfig::default()
+        };
+        let notifiers = [notifier("hook", HealthState::Degraded), notifier("pager", HealthState::Degraded)];
+
+        let policy = Policy::new(config.clone(), vec![alert("a", HealthState::Down)]);
+        assert!(policy.due(&notifiers, at(99)).is_empty());
+        assert!(matches!(policy.due(&notifiers, at(100)).as_slice(), [Due::Escalation(1, a)] if a.escalated));
+        assert!(policy.due(&notifiers, at(500)).is_empty());
+
+        let policy = Policy::new(config, vec![alert("a", HealthState::Down)]);
+        policy.acknowledge("a", "ops", at(50));
+        assert!(policy.due(&notifiers, at(500)).is_empty());
+    }
+
+    #[test]
+    fn reconcile_closes_loaded_alert_of_healthy_target() {
+        let loaded = vec![alert("a", HealthState::Down), alert("b", HealthState::Down)];
+        let policy = Policy::new(PolicyConfig::default(), loaded);
+
+        assert!(policy.reconcile("a", HealthState::Down).is_none());
+        assert!(policy.reconcile("b", HealthState::Degraded).is_none());
+        let closed = policy.reconcile("a", HealthState::Healthy).unwrap();
+        assert_eq!(closed.state, HealthState::Down);
+
+        let open: Vec<String> = policy.alerts().into_iter().map(|a| a.target).collect();
+        assert_eq!(open, vec!["b"]);
+        assert!(policy.reconcile("a", HealthState::Healthy).is_none());
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Remembers when a block height last advanced
+#[derive(Clone, Debug, Default)]
+pub struct HeightTracker {
+    last: Option<(i64, Instant)>,
+}
+
+impl HeightTracker {
+    /// Records a height and returns how long it has been since the height last advanced
+    pub fn observe(&mut self, height: i64, now: Instant) -> Duration {
+        match self.last {
+            Some((last, since)) if height <= last => now.duration_since(since),
+            _ => {
+                self.last = Some((height, now));
+                Duration::ZERO
+            },
+        }
+    }
+}
+
+/// Indexing and chain progress over a sliding time window
+#[derive(Clone, Debug, Default)]
+pub struct RateWindow {
+    /// (time, synced block, chain head), oldest first
+    samples: VecDeque<(Instant, i64, i64)>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SyncRates {
+    /// Blocks indexed per second
+    pub indexing: f64,
+    /// Blocks produced by the chain per second
+    pub chain: f64,
+}
+
+impl RateWindow {
+    /// Records a sample, drops the ones older than `window` and returns the rates over the
+    /// remaining samples, `None` until the window spans some time
+    pub fn observe(&mut self, synced: i64, head: i64, now: Instant, window: Duration) -> Option<SyncRates> {
+        self.samples.push_back((now, synced, head));
+        while self
+            .samples
+            .front()
+            .is_some_and(|(at, _, _)| now.duration_since(*at) > window)
+        {
+            self.samples.pop_front();
+        }
+
+        let (first_at, first_synced, first_head) = *self.samples.front()?;
+        let elapsed = now.duration_since(first_at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+        Some(SyncRates {
+            indexing: (synced - first_synced) as f64 / elapsed,
+            chain: (head - first_head) as f64 / elapsed,
+        })
+    }
+}
+
+impl SyncRates {
+    /// Seconds until the subgraph reaches the chain head at the current rates,
+    /// infinite if it is not catching up
+    pub fn eta_seconds(&self, blocks_behind: i64) -> f64 {
+        if blocks_behind <= 0 {
+            return 0.0;
+        }
+        let catch_up = self.indexing - self.chain;
+        if catch_up > 0.0 {
+            blocks_behind as f64 / catch_up
+        } else {
+            f64::INFINITY
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use crate::health::{HealthState, Reason};
+use crate::history::CheckRecord;
+use crate::policy::Alert;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Settings of the optional SQLite store
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StorageConfig {
+    /// Database file, created if missing
+    pub path: PathBuf,
+    /// Days checks are kept
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u64,
+    /// Days health transitions and deployment changes are kept
+    #[serde(default = "default_transition_retention_days")]
+    pub transition_retention_days: u64,
+    /// Hours after which checks are thinned out to one per state and `compact_bucket`
+    #[serde(default = "default_compact_after_hours")]
+    pub compact_after_hours: u64,
+    /// Seconds covered by a single check once compacted
+    #[serde(default = "default_compact_bucket")]
+    pub compact_bucket: u64,
+    /// Seconds between retention and compaction runs
+    #[serde(default = "default_maintenance_interval")]
+    pub maintenance_interval: u64,
+}
+
+fn default_retention_days() -> u64 {
+    30
+}
+
+fn default_transition_retention_days() -> u64 {
+    365
+}
+
+fn default_compact_after_hours() -> u64 {
+    24
+}
+
+fn default_compact_bucket() -> u64 {
+    600
+}
+
+fn default_maintenance_interval() -> u64 {
+    3600
+}
+
+impl StorageConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.compact_bucket == 0 || self.maintenance_interval == 0 {
+            return Err("storage compact_bucket and maintenance_interval must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Change of the reported state of a target
+#[derive(Serialize, Clone, Debug)]
+pub struct Transition {
+    pub target: String,
+    pub at: DateTime<Utc>,
+    pub from: HealthState,
+    pub to: HealthState,
+    pub reasons: Vec<Reason>,
+}
+
+/// Change of the deployment served by a target's subgraph endpoint
+#[derive(Serialize, Clone, Debug)]
+pub struct DeploymentChange {
+    pub target: String,
+    pub at: DateTime<Utc>,
+    pub from: Option<String>,
+    pub to: String,
+}
+
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS checks (
+    id INTEGER PRIMARY KEY,
+    target TEXT NOT NULL,
+    checked_at INTEGER NOT NULL,
+    record TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS checks_target_time ON checks (target, checked_at);
+CREATE TABLE IF NOT EXISTS transitions (
+    id INTEGER PRIMARY KEY,
+    target TEXT NOT NULL,
+    at INTEGER NOT NULL,
+    from_state TEXT NOT NULL,
+    to_state TEXT NOT NULL,
+    reasons TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS transitions_target_time ON transitions (target, at);
+CREATE TABLE IF NOT EXISTS deployment_changes (
+    id INTEGER PRIMARY KEY,
+    target TEXT NOT NULL,
+    at INTEGER NOT NULL,
+    from_deployment TEXT,
+    to_deployment TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS deployment_changes_target_time ON deployment_changes (target, at);
+CREATE TABLE IF NOT EXISTS alerts (
+    target TEXT PRIMARY KEY,
+    alert TEXT NOT NULL
+);
+";
+
+fn to_json_error(e: serde_json::Error) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(Box::new(e))
+}
+
+fn from_json_error(e: serde_json::Error) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+}
+
+fn from_millis(millis: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+}
+
+impl Store {
+    pub fn open(path: &Path) -> rusqlite::Result<Store> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Store { conn: Mutex::new(conn) })
+    }
+
+    /// Stores a check together with the transition it caused, if any
+    pub fn insert_check(&self, target: &str, record: &CheckRecord, transition: Option<&Transition>) -> rusqlite::Result<()> {
+        let json = serde_json::to_string(record).map_err(to_json_error)?;
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO checks (target, checked_at, record) VALUES (?1, ?2, ?3)",
+            params![target, record.checked_at.timestamp_millis(), json],
+        )?;
+        if let Some(transition) = transition {
+            let reasons = serde_json::to_string(&transition.reasons).map_err(to_json_error)?;
+            tx.execute(
+                "INSERT INTO transitions (target, at, from_state, to_state, reasons) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    transition.target,
+                    transition.at.timestamp_millis(),
+                    transition.from.as_str(),
+                    transition.to.as_str(),
+                    reasons,
+                ],
+            )?;
+        }
+        tx.commit()
+    }
+
+    pub fn insert_deployment_change(&self, change: &DeploymentChange) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO deployment_changes (target, at, from_deployment, to_deployment) VALUES (?1, ?2, ?3, ?4)",
+            params![change.target, change.at.timestamp_millis(), change.from, change.to],
+        )?;
+        Ok(())
+    }
+
+    /// Stores the open alert of a target, replacing the previous one
+    pub fn upsert_alert(&self, alert: &Alert) -> rusqlite::Result<()> {
+        let json = serde_json::to_string(alert).map_err(to_json_error)?;
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO alerts (target, alert) VALUES (?1, ?2)
+             ON CONFLICT (target) DO UPDATE SET alert = excluded.alert",
+            params![alert.target, json],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_alert(&self, target: &str) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute("DELETE FROM alerts WHERE target = ?1", params![target])?;
+        Ok(())
+    }
+
+    /// Open alerts of all targets
+    pub fn alerts(&self) -> rusqlite::Result<Vec<Alert>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT alert FROM alerts")?;
+        let alerts = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|json| serde_json::from_str(&json?).map_err(from_json_error))
+            .collect();
+        alerts
+    }
+
+    /// The most recent `limit` checks of a target, oldest first
+    pub fn recent_checks(&self, target: &str, limit: usize) -> rusqlite::Result<Vec<CheckRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT record FROM checks WHERE target = ?1 ORDER BY checked_at DESC LIMIT ?2",
+        )?;
+        let mut records = stmt
+            .query_map(params![target, limit as i64], |row| row.get::<_, String>(0))?
+            .map(|json| serde_json::from_str(&json?).map_err(from_json_error))
+            .collect::<rusqlite::Result<Vec<CheckRecord>>>()?;
+        records.reverse();
+        Ok(records)
+    }
+
+    /// The most recent `limit` transitions within `[from, to]`, newest first
+    pub fn transitions(
+        &self,
+        target: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<Transition>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT target, at, from_state, to_state, reasons FROM transitions
+             WHERE (?1 IS NULL OR target = ?1) AND at >= ?2 AND at <= ?3
+             ORDER BY at DESC LIMIT ?4",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                target,
+                from.map_or(i64::MIN, |t| t.timestamp_millis()),
+                to.map_or(i64::MAX, |t| t.timestamp_millis()),
+                limit as i64,
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            },
+        )?;
+
+        rows.map(|row| {
+            let (target, at, from, to, reasons) = row?;
+            let state = |s: String| serde_json::from_value(serde_json::Value::String(s)).map_err(from_json_error);
+            Ok(Transition {
+                target,
+                at: from_millis(at),
+                from: state(from)?,
+                to: state(to)?,
+                reasons: serde_json::from_str(&reasons).map_err(from_json_error)?,
+            })
+        })
+        .collect()
+    }
+
+    /// The most recent `limit` deployment changes within `[from, to]`, newest first
+    pub fn deployment_changes(
+        &self,
+        target: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<DeploymentChange>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT target, at, from_deployment, to_deployment FROM deployment_changes
+             WHERE (?1 IS NULL OR target = ?1) AND at >= ?2 AND at <= ?3
+             ORDER BY at DESC LIMIT ?4",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                target,
+                from.map_or(i64::MIN, |t| t.timestamp_millis()),
+                to.map_or(i64::MAX, |t| t.timestamp_millis()),
+                limit as i64,
+            ],
+            |row| {
+                Ok(DeploymentChange {
+                    target: row.get(0)?,
+                    at: from_millis(row.get(1)?),
+                    from: row.get(2)?,
+                    to: row.get(3)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    /// Drops checks and transitions past their retention and thins out old checks,
+    /// returns the number of deleted checks
+    pub fn maintain(&self, config: &StorageConfig) -> rusqlite::Result<usize> {
+        let now = Utc::now().timestamp_millis();
+        let day = 24 * 3600 * 1000;
+        let conn = self.conn.lock().unwrap();
+
+        let mut deleted = conn.execute(
+            "DELETE FROM checks WHERE checked_at < ?1",
+            params![now - config.retention_days as i64 * day],
+        )?;
+        conn.execute(
+            "DELETE FROM transitions WHERE at < ?1",
+            params![now - config.transition_retention_days as i64 * day],
+        )?;
+        conn.execute(
+            "DELETE FROM deployment_changes WHERE at < ?1",
+            params![now - config.transition_retention_days as i64 * day],
+        )?;
+
+        // keep the first check of every state per bucket, so outages stay visible
+        let compact_before = now - config.compact_after_hours as i64 * 3600 * 1000;
+        deleted += conn.execute(
+            "DELETE FROM checks WHERE checked_at < ?1 AND id NOT IN (
+                 SELECT MIN(id) FROM checks WHERE checked_at < ?1
+                 GROUP BY target, checked_at / ?2, json_extract(record, '$.state')
+             )",
+            params![compact_before, config.compact_bucket as i64 * 1000],
+        )?;
+
+        Ok(deleted)
+    }
+}
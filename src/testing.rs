@@ -0,0 +1,6 @@
+use chrono::{DateTime, Utc};
+
+/// A fixed time, `seconds` after an arbitrary start
+pub fn at(seconds: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap()
+}